@@ -6,6 +6,7 @@ use reqwest::{Client, Result};
 #[derive(Deserialize, Debug)]
 struct Gist {
     id: String,
+    #[allow(dead_code)]
     html_url: String,
 }
 
@@ -0,0 +1,253 @@
+use crate::{
+    auth::SpotifyCredentials,
+    cache::{match_cache_path, MatchCache},
+    cli::{check_formats, parse_account, with_library, AudioFormat, Config},
+    downloader::{process_queue, queue_entity, retag_track},
+    error::Error,
+    models::{
+        cli::{Bitrate, Codec, Source},
+        spotify::{SpotifyEntity, SpotifyLink},
+    },
+    output::{set_format, Event, OutputFormat},
+    overrides::Overrides,
+    plan::{plan_tracks, PlannedTrack},
+    provider::{default_provider, MetadataProvider},
+    report::{ReportEntry, RunReport},
+    source::AudioSource,
+};
+use std::{fmt, path::PathBuf, sync::Arc};
+use tokio::sync::mpsc::UnboundedSender;
+
+// everything a download needs, the same defaults as the command line
+pub struct DownloaderBuilder {
+    config: Config,
+    library: Option<PathBuf>,
+    events: Option<UnboundedSender<Event>>,
+    provider: Option<Box<dyn MetadataProvider>>,
+}
+
+impl fmt::Debug for DownloaderBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloaderBuilder")
+            .field("config", &self.config)
+            .field("library", &self.library)
+            .field("events", &self.events)
+            .field("provider", &self.provider.is_some())
+            .finish()
+    }
+}
+
+impl DownloaderBuilder {
+    pub fn new(client_id: impl Into<String>) -> Self {
+        DownloaderBuilder {
+            config: Config::new(SpotifyCredentials {
+                client_id: client_id.into(),
+                client_secret: None,
+                redirect_uri: None,
+            }),
+            library: None,
+            events: None,
+            provider: None,
+        }
+    }
+
+    // without it every request goes through the user's authorization with PKCE
+    pub fn client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.config.credentials.client_secret = Some(client_secret.into());
+        self
+    }
+
+    // only needed for private playlists and the liked songs
+    pub fn redirect_uri(mut self, redirect_uri: impl Into<String>) -> Self {
+        self.config.credentials.redirect_uri = Some(redirect_uri.into());
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.file_path = path.into();
+        self
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.config.codec = codec;
+        self
+    }
+
+    pub fn bitrate(mut self, bitrate: Bitrate) -> Self {
+        self.config.bitrate = bitrate;
+        self
+    }
+
+    // longest side of the embedded cover in pixels, 0 leaves the cover out
+    pub fn cover_size(mut self, cover_size: u32) -> Self {
+        self.config.cover_size = Some(cover_size);
+        self
+    }
+
+    // another format every track is written in, from the same download as the codec above
+    pub fn extra_format(mut self, format: AudioFormat) -> Self {
+        self.config.extra_formats.push(format);
+        self
+    }
+
+    pub fn parallel_downloads(mut self, parallel_downloads: u32) -> Self {
+        self.config.chunk = Some(parallel_downloads);
+        self
+    }
+
+    pub fn output_template(mut self, output_template: impl Into<String>) -> Self {
+        self.config.output_template = output_template.into();
+        self
+    }
+
+    pub fn overrides(mut self, overrides: Overrides) -> Self {
+        self.config.overrides = overrides;
+        self
+    }
+
+    // searches again instead of reusing the cached matches
+    pub fn rematch(mut self, rematch: bool) -> Self {
+        self.config.rematch = rematch;
+        self
+    }
+
+    // leaves tracks whose file already exists alone, like sync
+    pub fn skip_existing(mut self, skip_existing: bool) -> Self {
+        self.config.skip_existing = skip_existing;
+        self
+    }
+
+    // the account whose cached token is used for the user's authorization
+    pub fn account(mut self, account: impl Into<String>) -> Self {
+        self.config.account = account.into();
+        self
+    }
+
+    pub fn no_browser(mut self, no_browser: bool) -> Self {
+        self.config.no_browser = no_browser;
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.config.source = source.backend();
+        self
+    }
+
+    // searches and downloads the audio with a backend of the caller's own
+    pub fn audio_source(mut self, source: impl AudioSource + 'static) -> Self {
+        self.config.source = Arc::new(source);
+        self
+    }
+
+    // tracks found in this directory are copied or transcoded from there, whatever the source
+    pub fn library(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.library = Some(directory.into());
+        self
+    }
+
+    // library files already in the codec are symlinked into the path instead of copied. the links
+    // keep the tags of the library, writing spotify's would change the library files
+    pub fn link(mut self, link: bool) -> Self {
+        self.config.link = link;
+        self
+    }
+
+    // every event of a download or retag is sent here as it happens
+    pub fn events(mut self, events: UnboundedSender<Event>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    // resolves the links with another catalog or a test double instead of spotify,
+    // the spotify credentials are unused then
+    pub fn provider(mut self, provider: impl MetadataProvider + 'static) -> Self {
+        self.provider = Some(Box::new(provider));
+        self
+    }
+
+    // the library prints nothing on stdout, the events are its output.
+    // a program that already chose an output format keeps it
+    pub fn build(mut self) -> Result<Downloader, Error> {
+        parse_account(&self.config.account)
+            .map_err(|err| Error::Config(format!("Account {}: {}", self.config.account, err)))?;
+        check_formats(&self.config.formats())?;
+        set_format(OutputFormat::Silent);
+        self.config.source = with_library(self.config.source, self.library)?;
+
+        Ok(Downloader {
+            provider: self
+                .provider
+                .unwrap_or_else(|| default_provider(&self.config)),
+            cache: Arc::new(MatchCache::load(match_cache_path())),
+            config: self.config,
+            events: self.events,
+        })
+    }
+}
+
+// resolves links and downloads, plans or retags what they point to
+pub struct Downloader {
+    config: Config,
+    provider: Box<dyn MetadataProvider>,
+    cache: Arc<MatchCache>,
+    events: Option<UnboundedSender<Event>>,
+}
+
+impl Downloader {
+    pub fn builder(client_id: impl Into<String>) -> DownloaderBuilder {
+        DownloaderBuilder::new(client_id)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // with spotify the clients are created on first use, the user is only asked to authorize when
+    // needed
+    pub async fn resolve(&mut self, link: &SpotifyLink) -> Result<SpotifyEntity, Error> {
+        self.provider.resolve(link).await
+    }
+
+    // matches every track without downloading anything
+    pub async fn plan(&self, entity: &SpotifyEntity) -> Vec<PlannedTrack> {
+        let file_path = entity.directory(&self.config.file_path);
+        plan_tracks(
+            entity.tracks().to_vec(),
+            file_path,
+            &self.config,
+            &self.cache,
+        )
+        .await
+    }
+
+    // downloads and tags every track, the outcome of each is in the returned entries
+    pub async fn download(&self, entity: &SpotifyEntity) -> Vec<ReportEntry> {
+        let report = Arc::new(self.report());
+        let queue = queue_entity(entity, &self.config).await;
+        process_queue(
+            queue,
+            Arc::new(self.config.clone()),
+            report.clone(),
+            self.cache.clone(),
+        )
+        .await;
+        report.entries()
+    }
+
+    // writes the current metadata and cover into tracks that were downloaded before
+    pub async fn tag(&self, entity: &SpotifyEntity) -> Vec<ReportEntry> {
+        let report = self.report();
+        let directory = entity.directory(&self.config.file_path);
+        for track in entity.tracks() {
+            retag_track(track.clone(), &self.config, &directory, &report).await;
+        }
+        report.entries()
+    }
+
+    fn report(&self) -> RunReport {
+        match &self.events {
+            Some(events) => RunReport::with_events(events.clone()),
+            None => RunReport::default(),
+        }
+    }
+}
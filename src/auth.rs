@@ -0,0 +1,335 @@
+use crate::error::Error;
+use crate::paths::{prepare_private_file, token_cache_path};
+use crate::prompt;
+use crate::settings::missing_setting;
+use crate::spotify::SpotifyClient;
+use colored::Colorize;
+use log::{error, info, warn};
+use reqwest::Url;
+use rspotify::{
+    prelude::*, scopes, AuthCodePkceSpotify, AuthCodeSpotify, ClientCredsSpotify, ClientError,
+    ClientResult, Config, Credentials, OAuth, Token,
+};
+use std::{
+    fs::remove_file,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    time::timeout,
+};
+
+// how long the local callback server waits for the browser to come back
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+const CALLBACK_RESPONSE: &str =
+    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n\
+<html><body><h3>yuck_premium is authorized, you can close this window.</h3></body></html>";
+
+// credentials of the app registered in the spotify developer dashboard
+#[derive(Clone, Debug)]
+pub struct SpotifyCredentials {
+    pub client_id: String,
+    // optional, without it the user flow falls back to PKCE
+    pub client_secret: Option<String>,
+    // only needed for the user's authorization
+    pub redirect_uri: Option<String>,
+}
+
+// client credentials need no browser or user, but can only read public albums, tracks and playlists.
+// without a client secret every request goes through the user's authorization instead
+pub async fn public_client(
+    credentials: &SpotifyCredentials,
+    no_browser: bool,
+    account: &str,
+) -> Result<SpotifyClient, Error> {
+    let Some(client_secret) = &credentials.client_secret else {
+        return user_authorization(credentials, no_browser, account).await;
+    };
+
+    let spotify_client =
+        ClientCredsSpotify::new(Credentials::new(&credentials.client_id, client_secret));
+    spotify_client.request_token().await.map_err(|err| {
+        Error::spotify_auth("Couldn't authenticate with the client credentials", err)
+    })?;
+
+    Ok(SpotifyClient::Public(spotify_client))
+}
+
+// authorization code flow on behalf of a user, needed for private playlists and the user's library.
+// the token of every account is cached separately and reused until it can't be refreshed
+pub async fn user_authorization(
+    credentials: &SpotifyCredentials,
+    no_browser: bool,
+    account: &str,
+) -> Result<SpotifyClient, Error> {
+    authorize_user(credentials, no_browser, account, true).await
+}
+
+// authorizes the account again even if a cached token is still valid
+pub async fn login(
+    credentials: &SpotifyCredentials,
+    no_browser: bool,
+    account: &str,
+) -> Result<SpotifyClient, Error> {
+    authorize_user(credentials, no_browser, account, false).await
+}
+
+// an empty cache left by a login that never finished is removed too, but doesn't count as logged in
+pub fn logout(account: &str) -> std::io::Result<bool> {
+    let path = token_cache_path(account);
+    if !path.exists() {
+        return Ok(false);
+    }
+    let logged_in = cached_token(account).is_some();
+    remove_file(path)?;
+    Ok(logged_in)
+}
+
+// the cache file is created before the user is asked, a failed or cancelled login leaves it empty
+fn discard_unused_cache(account: &str) {
+    let path = token_cache_path(account);
+    if path.exists() && cached_token(account).is_none() {
+        if let Err(err) = remove_file(&path) {
+            warn!("Token cache {:?} couldn't be removed: {}", path, err);
+        }
+    }
+}
+
+// the cached token of an account, None if it never logged in
+pub fn cached_token(account: &str) -> Option<Token> {
+    Token::from_cache(token_cache_path(account)).ok()
+}
+
+fn user_config(account: &str) -> Config {
+    let cache_path = token_cache_path(account);
+    let token_cached = match prepare_private_file(&cache_path) {
+        Ok(_) => true,
+        Err(err) => {
+            error!("Token cache {:?} couldn't be created: {}", cache_path, err);
+            false
+        }
+    };
+
+    Config {
+        cache_path,
+        token_cached,
+        ..Default::default()
+    }
+}
+
+async fn authorize_user(
+    credentials: &SpotifyCredentials,
+    no_browser: bool,
+    account: &str,
+    reuse_cached: bool,
+) -> Result<SpotifyClient, Error> {
+    let redirect_uri = credentials
+        .redirect_uri
+        .clone()
+        .ok_or_else(|| Error::Config(missing_setting("redirect_uri")))?;
+
+    // define the scopes needed for downloading
+    let oauth = OAuth {
+        redirect_uri,
+        scopes: scopes!(
+            "playlist-read-private",
+            "playlist-read-collaborative",
+            "user-read-currently-playing",
+            "playlist-modify-public",
+            "playlist-modify-private",
+            "user-library-read",
+            "user-library-modify",
+            "user-read-private"
+        ),
+        ..Default::default()
+    };
+
+    match &credentials.client_secret {
+        Some(client_secret) => {
+            let creds = Credentials::new(&credentials.client_id, client_secret);
+            let spotify_client = AuthCodeSpotify::with_config(creds, oauth, user_config(account));
+
+            // obtaining the access token, returns the redirect uri
+            // HTTP/1.1 302 FOUND
+            // location: https://client.example.com/cb?code=authorizationcode&state=oauth
+            let url = spotify_client
+                .get_authorize_url(false)
+                .map_err(|err| Error::spotify_auth("Couldn't build the authorization URL", err))?;
+            authorize(&spotify_client, &url, no_browser, reuse_cached)
+                .await
+                .map_err(|err| {
+                    discard_unused_cache(account);
+                    Error::spotify_auth("Authorization failed", err)
+                })?;
+
+            Ok(SpotifyClient::User(spotify_client))
+        }
+        None => {
+            let creds = Credentials::new_pkce(&credentials.client_id);
+            let mut spotify_client =
+                AuthCodePkceSpotify::with_config(creds, oauth, user_config(account));
+
+            // generates the code verifier the token request is checked against
+            let url = spotify_client
+                .get_authorize_url(None)
+                .map_err(|err| Error::spotify_auth("Couldn't build the authorization URL", err))?;
+            authorize(&spotify_client, &url, no_browser, reuse_cached)
+                .await
+                .map_err(|err| {
+                    discard_unused_cache(account);
+                    Error::spotify_auth("Authorization failed", err)
+                })?;
+
+            Ok(SpotifyClient::Pkce(spotify_client))
+        }
+    }
+}
+
+// same as rspotify's prompt_for_token, but the code comes from the callback server or a pasted url
+async fn authorize(
+    client: &impl OAuthClient,
+    url: &str,
+    no_browser: bool,
+    reuse_cached: bool,
+) -> ClientResult<()> {
+    let cached = if reuse_cached {
+        client.read_token_cache(true).await
+    } else {
+        Ok(None)
+    };
+
+    match cached {
+        Ok(Some(new_token)) => {
+            let expired = new_token.is_expired();
+
+            // the cached token is loaded even when expired, it's refreshed right after
+            set_token(client, new_token).await?;
+
+            if expired {
+                match client.refetch_token().await? {
+                    Some(refreshed_token) => {
+                        info!("Refreshed the expired token from the token cache");
+                        set_token(client, refreshed_token).await?;
+                    }
+                    None => {
+                        info!("Expired token couldn't be refreshed");
+                        let code = get_code(client, url, no_browser).await?;
+                        client.request_token(&code).await?;
+                    }
+                }
+            }
+        }
+        _ => {
+            let code = get_code(client, url, no_browser).await?;
+            client.request_token(&code).await?;
+        }
+    }
+
+    client.write_token_cache().await
+}
+
+async fn set_token(client: &impl OAuthClient, token: Token) -> ClientResult<()> {
+    let token_lock = client.get_token();
+    let mut current = token_lock
+        .lock()
+        .await
+        .map_err(|_| ClientError::Cli("the token is held by a failed request".to_string()))?;
+    *current = Some(token);
+    Ok(())
+}
+
+async fn get_code(client: &impl OAuthClient, url: &str, no_browser: bool) -> ClientResult<String> {
+    if !no_browser {
+        match listen_for_callback(client, url).await {
+            Ok(code) => return Ok(code),
+            Err(err) => warn!("{}, falling back to pasting the redirect url", err),
+        }
+    }
+
+    paste_redirect_url(client, url)
+}
+
+// opens the browser and waits for spotify to redirect it to the local callback server
+async fn listen_for_callback(client: &impl OAuthClient, url: &str) -> Result<String, String> {
+    let redirect_uri = Url::parse(&client.get_oauth().redirect_uri)
+        .map_err(|err| format!("Redirect URI couldn't be parsed: {}", err))?;
+
+    // the listener binds the address family the browser is redirected to
+    let host = redirect_uri.host_str().unwrap_or_default();
+    let address = match host {
+        "localhost" | "127.0.0.1" => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        "[::1]" => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        _ => return Err(format!("Redirect URI host {} isn't local", host)),
+    };
+    let port = redirect_uri
+        .port_or_known_default()
+        .ok_or_else(|| "Redirect URI has no port".to_string())?;
+
+    let listener = TcpListener::bind((address, port))
+        .await
+        .map_err(|err| format!("Couldn't listen on port {}: {}", port, err))?;
+
+    match webbrowser::open(url) {
+        Ok(_) => prompt!("Opened {} in your browser.", url),
+        Err(_) => prompt!("Please open this URL in your browser: {}", url),
+    }
+    prompt!("{}", "Waiting for the authorization...".cyan());
+
+    timeout(CALLBACK_TIMEOUT, async {
+        loop {
+            let (mut stream, _) = listener
+                .accept()
+                .await
+                .map_err(|err| format!("Callback connection failed: {}", err))?;
+
+            let mut buffer = [0; 8192];
+            let read = stream
+                .read(&mut buffer)
+                .await
+                .map_err(|err| format!("Callback request couldn't be read: {}", err))?;
+            let request = String::from_utf8_lossy(&buffer[..read]);
+
+            // GET /callback?code=...&state=... HTTP/1.1
+            let Some(path) = request
+                .lines()
+                .next()
+                .and_then(|line| line.split(' ').nth(1))
+            else {
+                continue;
+            };
+            let Ok(callback) = redirect_uri.join(path) else {
+                continue;
+            };
+
+            // browsers also ask for a favicon, only the redirect carries the code
+            if let Some(code) = client.parse_response_code(callback.as_str()) {
+                let _ = stream.write_all(CALLBACK_RESPONSE.as_bytes()).await;
+                return Ok(code);
+            }
+            if callback.query_pairs().any(|(key, _)| key == "error") {
+                return Err(format!("Authorization was denied: {}", callback));
+            }
+        }
+    })
+    .await
+    .map_err(|_| "Timed out waiting for the authorization".to_string())?
+}
+
+// for ssh sessions, the url is opened on any machine and the redirect pasted back
+fn paste_redirect_url(client: &impl OAuthClient, url: &str) -> ClientResult<String> {
+    prompt!(
+        "Open this URL in a browser and authorize the app:\n\n{}\n",
+        url
+    );
+    prompt!("Please enter the URL you were redirected to: ");
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+
+    client
+        .parse_response_code(input.trim())
+        .ok_or_else(|| ClientError::Cli("unable to parse the response code".to_string()))
+}
@@ -0,0 +1,151 @@
+use crate::matcher::Candidate;
+use crate::paths::cache_dir;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, read_to_string, rename, write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// matches older than this are searched again
+pub const MATCH_CACHE_EXPIRY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const MATCH_CACHE_FILE: &str = "matches.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedMatch {
+    pub video_id: String,
+    pub url: String,
+    pub title: String,
+    pub score: f64,
+    // seconds since the unix epoch
+    pub matched_at: u64,
+}
+
+impl CachedMatch {
+    pub fn is_expired(&self) -> bool {
+        now().saturating_sub(self.matched_at) > MATCH_CACHE_EXPIRY.as_secs()
+    }
+
+    pub fn to_candidate(&self) -> Candidate {
+        Candidate {
+            id: self.video_id.clone(),
+            url: self.url.clone(),
+            title: self.title.clone(),
+            duration: None,
+            score: self.score,
+            pinned: false,
+        }
+    }
+}
+
+// spotify track id to the video that was downloaded for it, kept between runs so that
+// re-downloads use the exact same source
+#[derive(Debug)]
+pub struct MatchCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, CachedMatch>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn match_cache_path() -> PathBuf {
+    cache_dir().join(MATCH_CACHE_FILE)
+}
+
+impl MatchCache {
+    // a missing or unreadable cache starts out empty, it only ever saves searches
+    pub fn load(path: PathBuf) -> MatchCache {
+        let entries = match read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                error!("Match cache {:?} couldn't be parsed: {}", path, err);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        MatchCache {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    // returns the cached match of a track unless it has expired
+    pub fn get(&self, spotify_id: &str) -> Option<CachedMatch> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(spotify_id)
+            .filter(|cached| !cached.is_expired())
+            .cloned()
+    }
+
+    pub fn entries(&self) -> Vec<(String, CachedMatch)> {
+        let mut entries: Vec<(String, CachedMatch)> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, cached)| (id.clone(), cached.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    pub fn insert(&self, spotify_id: &str, candidate: &Candidate) {
+        if spotify_id.is_empty() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(
+            spotify_id.to_string(),
+            CachedMatch {
+                video_id: candidate.id.clone(),
+                url: candidate.url.clone(),
+                title: candidate.title.clone(),
+                score: candidate.score,
+                matched_at: now(),
+            },
+        );
+        self.save(&entries);
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.save(&entries);
+    }
+
+    // written next to the cache and renamed over it so a crash can't leave half a file
+    fn save(&self, entries: &HashMap<String, CachedMatch>) {
+        if let Some(parent) = self.path.parent() {
+            if let Err(err) = create_dir_all(parent) {
+                error!("Directory {:?} couldn't be created: {}", parent, err);
+                return;
+            }
+        }
+
+        let contents = match serde_json::to_string_pretty(entries) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Match cache couldn't be serialized: {}", err);
+                return;
+            }
+        };
+
+        let staging = self.path.with_extension("json.tmp");
+        match write(&staging, contents).and_then(|_| rename(&staging, &self.path)) {
+            Ok(_) => info!("Match cache saved to {:?}", self.path),
+            Err(err) => error!("Match cache {:?} couldn't be saved: {}", self.path, err),
+        }
+    }
+}
@@ -1,83 +1,463 @@
-use crate::models::cli::{Bitrate, Codec};
-use crate::models::spotify::Spotify;
-use clap::builder::PossibleValue;
-use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
-use std::path::PathBuf;
-use std::u32;
+use crate::auth::{cached_token, login, logout, SpotifyCredentials};
+use crate::cache::{match_cache_path, MatchCache};
+use crate::error::Error;
+use crate::export::ExportFormat;
+use crate::input::STDIN;
+use crate::local::LocalLibrary;
+use crate::models::cli::{Bitrate, Codec, Source};
+use crate::models::spotify::SpotifyLink;
+use crate::output::OutputFormat;
+use crate::overrides::Overrides;
+use crate::paths::token_cache_path;
+use crate::settings::{config_path, FormatSettings, Settings};
+use crate::source::AudioSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum};
+use colored::Colorize;
+use std::{path::PathBuf, sync::Arc};
+
+// used when neither the config file, the environment nor the flags set them
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "{title}";
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub id: String,
-    pub spotify_type: Spotify,
     pub file_path: PathBuf,
     pub codec: Codec,
     pub bitrate: Bitrate,
     pub chunk: Option<u32>,
+    pub output_template: String,
+    pub overrides: Overrides,
+    pub rematch: bool,
+    pub dry_run: bool,
+    pub plan_file: Option<PathBuf>,
+    // tracks whose file already exists are left alone, set by sync
+    pub skip_existing: bool,
+    pub no_browser: bool,
+    pub account: String,
+    pub credentials: SpotifyCredentials,
+    // where the audio is searched for and downloaded from, youtube unless chosen otherwise
+    pub source: Arc<dyn AudioSource>,
+    // library files already in the codec are symlinked instead of copied, and keep their own tags
+    pub link: bool,
+    // longest side of the embedded cover in pixels, the cover as spotify has it when unset
+    pub cover_size: Option<u32>,
+    // written from the same download as the codec above, which sync and retag go by first
+    pub extra_formats: Vec<AudioFormat>,
+}
+
+// a codec every track is written in, with the bitrate, root directory and cover size of its own
+#[derive(Debug, Clone)]
+pub struct AudioFormat {
+    pub codec: Codec,
+    pub bitrate: Bitrate,
+    pub file_path: PathBuf,
+    pub cover_size: Option<u32>,
+}
+
+// every subcommand only defines the options it needs, the rest are left to the other layers
+fn value<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Option<T> {
+    matches.try_get_one::<T>(id).ok().flatten().cloned()
+}
+
+fn flag(matches: &ArgMatches, id: &str) -> bool {
+    value::<bool>(matches, id).unwrap_or(false)
+}
+
+fn values<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Option<Vec<T>> {
+    matches
+        .try_get_many::<T>(id)
+        .ok()
+        .flatten()
+        .map(|values| values.cloned().collect())
+}
+
+// "opus=128" is the value of one codec, a value without a codec the one of every codec
+fn per_codec<T: 'static>(
+    parse: fn(&str) -> Result<T, String>,
+) -> impl Fn(&str) -> Result<(Option<Codec>, T), String> + Clone + Send + Sync + 'static {
+    move |value: &str| {
+        if let Some((codec, rest)) = value.split_once('=') {
+            // a path may have a "=" in it, only a codec before it makes it a codec's value
+            if let Ok(codec) = Codec::from_str(codec, true) {
+                return Ok((Some(codec), parse(rest)?));
+            }
+        }
+        Ok((None, parse(value)?))
+    }
+}
+
+fn parse_bitrate(bitrate: &str) -> Result<Bitrate, String> {
+    Bitrate::from_str(bitrate, true).map_err(|_| format!("{} isn't a supported bitrate", bitrate))
+}
+
+fn parse_path(path: &str) -> Result<PathBuf, String> {
+    Ok(PathBuf::from(path))
+}
+
+fn parse_cover_size(size: &str) -> Result<u32, String> {
+    size.parse::<u32>()
+        .map_err(|_| format!("{} isn't a number of pixels", size))
+}
+
+// the values given for a single codec go into its format, the rest into the one shared by every codec
+fn per_codec_values<T: Clone + Send + Sync + 'static>(
+    matches: &ArgMatches,
+    id: &str,
+    settings: &mut Settings,
+    shared: &mut FormatSettings,
+    set: fn(&mut FormatSettings, T),
+) {
+    for (codec, value) in values::<(Option<Codec>, T)>(matches, id).unwrap_or_default() {
+        match codec {
+            Some(codec) => set(settings.formats.entry(codec).or_default(), value),
+            None => set(shared, value),
+        }
+    }
+}
+
+// the settings given as flags, the last layer on top of the config file and the environment
+fn settings_from_matches(matches: &ArgMatches) -> Settings {
+    let mut settings = Settings {
+        codec: values(matches, "codec"),
+        output_template: value(matches, "output_template"),
+        chunk: value(matches, "chunk"),
+        overrides: value(matches, "overrides"),
+        account: value(matches, "account"),
+        source: value(matches, "source"),
+        library: value(matches, "library"),
+        link: flag(matches, "link").then_some(true),
+        ..Default::default()
+    };
+
+    let mut shared = FormatSettings::default();
+    per_codec_values(
+        matches,
+        "bitrate",
+        &mut settings,
+        &mut shared,
+        |format, bitrate| format.bitrate = Some(bitrate),
+    );
+    per_codec_values(
+        matches,
+        "file_path",
+        &mut settings,
+        &mut shared,
+        |format, path| format.path = Some(path),
+    );
+    per_codec_values(
+        matches,
+        "cover_size",
+        &mut settings,
+        &mut shared,
+        |format, size| format.cover_size = Some(size),
+    );
+
+    Settings {
+        bitrate: shared.bitrate,
+        path: shared.path,
+        cover_size: shared.cover_size,
+        ..settings
+    }
+}
+
+pub fn load_settings(matches: &ArgMatches) -> Result<Settings, Error> {
+    let config_file = value::<PathBuf>(matches, "config");
+    let settings = Settings::load(config_file.as_deref())
+        .map_err(Error::Config)?
+        .merge(settings_from_matches(matches));
+
+    // nothing would ever be downloaded with no parallel downloads, the flag can't be 0 either
+    if settings.chunk == Some(0) {
+        return Err(Error::Config(
+            "chunk has to be at least 1 in the config file".to_string(),
+        ));
+    }
+
+    // accounts from the config file or the environment go through the same check as the flag
+    if let Some(account) = &settings.account {
+        parse_account(account)
+            .map_err(|err| Error::Config(format!("Account {}: {}", account, err)))?;
+    }
+
+    Ok(settings)
 }
 
 impl Config {
-    pub fn parse_config(matches: &ArgMatches) -> Config {
-        // clap checks for required flags during the entry, so it is safe to unwrap the value here
-        let spotify_id: String = matches.get_one::<String>("spotify_id").unwrap().to_string();
-        let file_path_prelim: String = matches.get_one::<String>("file_path").unwrap().to_string();
-        let file_path = PathBuf::from(file_path_prelim);
-
-        let spotify_type_prelim = matches.get_one::<String>("spotify");
-
-        let spotify_type = match spotify_type_prelim {
-            Some(s) => match s.to_owned().as_str() {
-                "track" => Spotify::Track,
-                "playlist" => Spotify::Playlist,
-                "album" => Spotify::Album,
-                _ => panic!("Spotify type not supported!"),
-            },
-            None => panic!("None of the types were provided!"),
-        };
+    // the defaults used when neither the config file, the environment nor the flags set them
+    pub fn new(credentials: SpotifyCredentials) -> Config {
+        Config {
+            file_path: PathBuf::from("."),
+            codec: Codec::MP3,
+            bitrate: Bitrate::Best,
+            chunk: None,
+            output_template: DEFAULT_OUTPUT_TEMPLATE.to_string(),
+            overrides: Overrides::default(),
+            rematch: false,
+            dry_run: false,
+            plan_file: None,
+            skip_existing: false,
+            no_browser: false,
+            account: DEFAULT_ACCOUNT.to_string(),
+            credentials,
+            source: Source::Youtube.backend(),
+            link: false,
+            cover_size: None,
+            extra_formats: Vec::new(),
+        }
+    }
 
-        let codec = match matches.get_one::<String>("codec") {
-            Some(s) => match s.to_owned().as_str() {
-                "mp3" => Codec::MP3,
-                "mpa" => Codec::MPA,
-                "flac" => Codec::Flac,
-                "opus" => Codec::Opus,
-                _ => panic!("File type is not supported!"),
-            },
-            None => panic!("None of the file types were provided!"),
-        };
+    // the codec of the config first, then the extra formats
+    pub fn formats(&self) -> Vec<AudioFormat> {
+        let mut formats = vec![AudioFormat {
+            codec: self.codec,
+            bitrate: self.bitrate,
+            file_path: self.file_path.clone(),
+            cover_size: self.cover_size,
+        }];
+        formats.extend(self.extra_formats.iter().cloned());
+        formats
+    }
+
+    // the config of a run that writes only the format
+    pub fn with_format(&self, format: &AudioFormat) -> Config {
+        Config {
+            file_path: format.file_path.clone(),
+            codec: format.codec,
+            bitrate: format.bitrate,
+            cover_size: format.cover_size,
+            extra_formats: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    pub fn parse_config(matches: &ArgMatches) -> Result<Config, Error> {
+        let settings = load_settings(matches)?;
+        let credentials = settings.credentials().map_err(Error::Config)?;
+        Config::from_settings(matches, &settings, credentials)
+    }
 
-        let bitrate = match matches.get_one::<String>("bitrate") {
-            Some(s) => match s.to_owned().as_str() {
-                "worst" => Bitrate::Worst,
-                "worse" => Bitrate::Worse,
-                "poor" => Bitrate::Poor,
-                "low" => Bitrate::Low,
-                "medium" => Bitrate::Medium,
-                "good" => Bitrate::Good,
-                "high" => Bitrate::High,
-                "best" => Bitrate::Best,
-                _ => panic!("Bitrate not supported!"),
-            },
-            None => panic!("Bitrate not found!"),
+    // for the commands that never talk to spotify, the credentials may be missing there
+    pub fn parse_offline_config(matches: &ArgMatches) -> Result<Config, Error> {
+        let settings = load_settings(matches)?;
+        let credentials = settings.credentials().unwrap_or(SpotifyCredentials {
+            client_id: String::new(),
+            client_secret: None,
+            redirect_uri: None,
+        });
+        Config::from_settings(matches, &settings, credentials)
+    }
+
+    fn from_settings(
+        matches: &ArgMatches,
+        settings: &Settings,
+        credentials: SpotifyCredentials,
+    ) -> Result<Config, Error> {
+        let defaults = Config::new(credentials);
+
+        let overrides = match &settings.overrides {
+            Some(path) => Overrides::load(path).map_err(Error::Config)?,
+            None => Overrides::default(),
         };
 
-        let chunk = matches.get_one::<u32>("chunk").copied();
+        let mut formats = audio_formats(settings, &defaults)?;
+        let primary = formats.remove(0);
 
-        Config {
-            id: spotify_id,
-            spotify_type,
-            file_path,
-            codec,
-            bitrate,
-            chunk,
+        Ok(Config {
+            file_path: primary.file_path,
+            codec: primary.codec,
+            bitrate: primary.bitrate,
+            cover_size: primary.cover_size,
+            extra_formats: formats,
+            chunk: settings.chunk,
+            output_template: settings
+                .output_template
+                .clone()
+                .unwrap_or(defaults.output_template),
+            overrides,
+            rematch: flag(matches, "rematch"),
+            dry_run: flag(matches, "dry_run"),
+            plan_file: value(matches, "plan_file"),
+            no_browser: flag(matches, "no_browser"),
+            account: settings.account.clone().unwrap_or(defaults.account),
+            source: with_library(
+                settings
+                    .source
+                    .map(Source::backend)
+                    .unwrap_or(defaults.source),
+                settings.library.clone(),
+            )?,
+            link: settings.link.unwrap_or(defaults.link),
+            ..defaults
+        })
+    }
+}
+
+// every codec once in the order given, each with its format settings or the shared ones
+fn audio_formats(settings: &Settings, defaults: &Config) -> Result<Vec<AudioFormat>, Error> {
+    let mut codecs: Vec<Codec> = Vec::new();
+    for codec in settings.codec.iter().flatten() {
+        if !codecs.contains(codec) {
+            codecs.push(*codec);
         }
     }
+    if codecs.is_empty() {
+        codecs.push(defaults.codec);
+    }
+
+    let formats: Vec<AudioFormat> = codecs
+        .into_iter()
+        .map(|codec| {
+            let format = settings.format(codec);
+            AudioFormat {
+                codec,
+                bitrate: format.bitrate.unwrap_or(defaults.bitrate),
+                file_path: format.path.unwrap_or_else(|| defaults.file_path.clone()),
+                cover_size: format.cover_size,
+            }
+        })
+        .collect();
+
+    check_formats(&formats)?;
+    Ok(formats)
+}
+
+// aac and alac are both .m4a, one would overwrite the other in the same directory
+pub fn check_formats(formats: &[AudioFormat]) -> Result<(), Error> {
+    for (index, format) in formats.iter().enumerate() {
+        if let Some(other) = formats[index + 1..].iter().find(|other| {
+            other.codec.extension() == format.codec.extension()
+                && other.file_path == format.file_path
+        }) {
+            return Err(Error::Config(format!(
+                "{} and {} both write .{} files into {:?}, give one of them its own path with --path {}=<dir>",
+                format.codec,
+                other.codec,
+                format.codec.extension(),
+                format.file_path,
+                other.codec
+            )));
+        }
+    }
+    Ok(())
 }
 
-pub async fn command_line() -> Config {
-    let commands = parser();
-    let matches = commands.get_matches();
+// tracks found in the library are taken from there, the source only searches for the rest
+pub fn with_library(
+    source: Arc<dyn AudioSource>,
+    library: Option<PathBuf>,
+) -> Result<Arc<dyn AudioSource>, Error> {
+    Ok(match library {
+        Some(directory) => Arc::new(LocalLibrary::new(directory, source)?),
+        None => source,
+    })
+}
+
+// links to download, given as arguments, from an input file or "-" for stdin
+fn links_args(required: bool) -> [Arg; 2] {
+    let urls = Arg::new("urls")
+        .value_name("url")
+        .num_args(1..)
+        .value_parser(|link: &str| match link {
+            STDIN => Ok(link.to_string()),
+            _ => link.parse::<SpotifyLink>().map(|_| link.to_string()),
+        })
+        .help("Spotify links or URIs of tracks, albums, playlists and artists, \"library\" for the liked songs or \"-\" to read them from stdin");
+
+    [
+        if required {
+            urls.required_unless_present("input_file")
+        } else {
+            urls
+        },
+        Arg::new("input_file")
+            .long("input-file")
+            .short('i')
+            .value_name("input_file")
+            .value_parser(value_parser!(PathBuf))
+            .help("File with one link per line, # starts a comment, \"-\" reads stdin"),
+    ]
+}
+
+// options shared by every subcommand that writes audio files
+fn output_args() -> [Arg; 6] {
+    [
+        Arg::new("file_path")
+            .long("path")
+            .value_name("file_path")
+            .action(ArgAction::Append)
+            .value_parser(per_codec(parse_path))
+            .help("Path where the audio file is to be downloaded, codec=path for one codec only [default: .]"),
+        Arg::new("codec")
+            .long("codec")
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(Codec))
+            .help("Codecs every track is written in, comma separated [default: mp3]"),
+        Arg::new("bitrate")
+            .long("bitrate")
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .value_parser(per_codec(parse_bitrate))
+            .help("Bitrate for the downloaded audio file, codec=bitrate for one codec only [default: best]"),
+        Arg::new("cover_size")
+            .long("cover-size")
+            .value_name("pixels")
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .value_parser(per_codec(parse_cover_size))
+            .help("Longest side of the embedded cover, 0 leaves it out, codec=pixels for one codec only"),
+        Arg::new("chunk")
+            .long("chunk")
+            .help("Number of parallel downloads at a time [default: 10]")
+            .value_parser(value_parser!(u32).range(1..)),
+        Arg::new("output_template")
+            .long("output-template")
+            .value_name("output_template")
+            .help("File name of every track, from {title}, {artists}, {album}, {track_number} and {disc_number} [default: {title}]"),
+    ]
+}
+
+// options of the subcommands that match and download tracks
+fn matching_args() -> [Arg; 5] {
+    [
+        Arg::new("library")
+            .long("library")
+            .value_name("library")
+            .value_parser(value_parser!(PathBuf))
+            .help("Directory of audio files already owned, tracks found there aren't downloaded"),
+        Arg::new("link")
+            .long("link")
+            .action(ArgAction::SetTrue)
+            .help("Symlink library files already in the codec instead of copying them, they keep their own tags"),
+        Arg::new("source")
+            .long("source")
+            .value_parser(value_parser!(Source))
+            .help("Where the audio of every track is searched for and downloaded from [default: youtube]"),
+        Arg::new("overrides")
+            .long("overrides")
+            .value_name("overrides")
+            .value_parser(value_parser!(PathBuf))
+            .help("TOML or JSON file mapping spotify track IDs to a video URL or \"skip\""),
+        Arg::new("rematch")
+            .long("rematch")
+            .action(ArgAction::SetTrue)
+            .help("Search again instead of reusing cached matches"),
+    ]
+}
 
-    Config::parse_config(&matches)
+// options of the subcommands that can match the tracks without downloading them
+fn dry_run_args() -> [Arg; 2] {
+    [
+        Arg::new("dry_run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Resolve and match the tracks without downloading anything"),
+        Arg::new("plan_file")
+            .long("plan-file")
+            .value_name("plan_file")
+            .value_parser(value_parser!(PathBuf))
+            .requires("dry_run")
+            .help("JSON or CSV file the dry run is written to instead of printing it"),
+    ]
 }
 
 pub fn parser() -> Command {
@@ -86,70 +466,365 @@ pub fn parser() -> Command {
         .about("\n\n
             ------------------------------------------------Hawk Tuah in your spotify premium thing!----------------------------------------------
 ")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
         .arg(
-            Arg::new("spotify")
-                .long("spotify")
-                .value_name("spotify")
-                .value_parser([
-                    PossibleValue::new("track"),
-                    PossibleValue::new("playlist"),
-                    PossibleValue::new("album"),
-                ])
-                .help("Name of the spotify entity to download!")
-                .required(true),
+            Arg::new("config")
+                .long("config")
+                .value_name("config")
+                .value_parser(value_parser!(PathBuf))
+                .global(true)
+                .help("Config file to read instead of config.toml in the config directory"),
         )
         .arg(
-            Arg::new("spotify_id")
-                .long("spotify_id")
-                .value_name("spotify_id")
-                .help("ID of the spotify entity to download!")
-                .required(true),
+            Arg::new("account")
+                .long("account")
+                .value_name("account")
+                .global(true)
+                .value_parser(parse_account)
+                .help("Name of the spotify account whose token is used [default: default]"),
         )
         .arg(
-            Arg::new("file_path")
-                .long("path")
-                .value_name("file_path")
-                .help("Path where the audio file is to be downloaded!")
-                .required(true),
+            Arg::new("no_browser")
+                .long("no-browser")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Print the authorization URL and paste the redirect URL back, for SSH sessions"),
         )
         .arg(
-            Arg::new("codec")
-                .long("codec")
-                // .value_names(["mp3", "mpa", "flac", "opus"])
-                .default_value("mp3")
-                .value_parser([
-                    PossibleValue::new("mp3"),
-                    PossibleValue::new("mpa"),
-                    PossibleValue::new("flac"),
-                    PossibleValue::new("opus"),
-                ])
-                .help("Name or ID of the spotify to download")
-                .required(true),
+            Arg::new("output")
+                .long("output")
+                .value_parser(value_parser!(OutputFormat))
+                .default_value("text")
+                .global(true)
+                .help("Print colored text, or one JSON event per line for scripts"),
         )
         .arg(
-            Arg::new("bitrate")
-                .long("bitrate")
-                .value_parser([
-                    PossibleValue::new("worst"),
-                    PossibleValue::new("worse"),
-                    PossibleValue::new("poor"),
-                    PossibleValue::new("low"),
-                    PossibleValue::new("medium"),
-                    PossibleValue::new("good"),
-                    PossibleValue::new("high"),
-                    PossibleValue::new("best"),
-                ])
-                .help("Name or ID of the spotify to download")
-                .required(true),
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .global(true)
+                .help("Log more details, -vv for debugging"),
         )
         .arg(
-            Arg::new("chunk")
-                .long("chunk")
-                .action(ArgAction::Append)
-                .help("Number of parallel downloads at a time")
-                .required(true)
-                .value_parser(value_parser!(u32)),
-        )
-    // .override_usage(
-    //     "yuck_premium [OPTIONS] --spotify --spotify_id <ID> --file_path <FILE_PATH> --codec <CODEC> --bitrate <BITRATE>"
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .global(true)
+                .help("Only log errors, without progress or a summary"),
+        )
+        .arg(
+            Arg::new("log_file")
+                .long("log-file")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Also write the log of the run to a file in the cache directory"),
+        )
+        .subcommand(
+            Command::new("download")
+                .about("Download tracks, albums, playlists or the liked songs")
+                .args(links_args(true))
+                .args(output_args())
+                .args(matching_args())
+                .args(dry_run_args()),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write the tracks of albums and playlists to CSV, JSON or M3U files without downloading them")
+                .args(links_args(true))
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .value_parser(value_parser!(ExportFormat))
+                        .value_delimiter(',')
+                        .action(ArgAction::Append)
+                        .default_value("csv")
+                        .help("Formats to write, separated by commas"),
+                )
+                .args(output_args()),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Download playlists exported to CSV or JSON files, without spotify")
+                .arg(
+                    Arg::new("files")
+                        .value_name("file")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Exportify CSV files, or CSV and JSON files in the native format"),
+                )
+                .args(output_args())
+                .args(matching_args())
+                .args(dry_run_args()),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search spotify and pick what to download from the results")
+                .arg(
+                    Arg::new("query")
+                        .value_name("query")
+                        .required(true)
+                        .num_args(1..)
+                        .help("Free text to search for"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_parser(value_parser!(u32).range(1..=50))
+                        .default_value("10")
+                        .help("Number of results"),
+                )
+                .arg(
+                    Arg::new("type")
+                        .long("type")
+                        .short('t')
+                        .value_parser(["track", "album", "artist", "playlist"])
+                        .default_value("track")
+                        .help("What to search for, picking an artist lists their albums"),
+                )
+                .args(output_args())
+                .args(matching_args()),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show the details of a track, album, playlist or artist without downloading it")
+                .arg(
+                    Arg::new("url")
+                        .value_name("url")
+                        .required(true)
+                        .value_parser(|link: &str| link.parse::<SpotifyLink>())
+                        .help("Spotify link or URI, or \"library\" for the liked songs"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the details as JSON for scripts"),
+                ),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Download only what's missing from the path, the liked songs by default")
+                .args(links_args(false))
+                .args(output_args())
+                .args(matching_args())
+                .arg(
+                    Arg::new("prune")
+                        .long("prune")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete files of tracks that were removed from an album or playlist"),
+                ),
+        )
+        .subcommand(
+            Command::new("retag")
+                .about("Write the spotify metadata and cover into files that were already downloaded")
+                .args(links_args(true))
+                .args(output_args()),
+        )
+        .subcommand(
+            Command::new("auth")
+                .about("Manage the cached spotify logins")
+                .subcommand_required(true)
+                .subcommand(Command::new("login").about("Authorize an account and cache its token"))
+                .subcommand(Command::new("logout").about("Remove the cached token of an account"))
+                .subcommand(Command::new("status").about("Show the cached token of an account")),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the configuration")
+                .subcommand_required(true)
+                .subcommand(Command::new("path").about("Print the path of the config file"))
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the settings from the config file and the environment"),
+                ),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Inspect the cache of matched videos")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List the cached matches"))
+                .subcommand(Command::new("clear").about("Remove every cached match")),
+        )
+}
+
+const DEFAULT_ACCOUNT: &str = "default";
+
+// account names become file names, so they are kept to a safe set of characters
+pub fn parse_account(account: &str) -> Result<String, String> {
+    if !account.is_empty()
+        && account
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(account.to_string())
+    } else {
+        Err("Account names may only contain letters, digits, '-' and '_'".to_string())
+    }
+}
+
+pub async fn run_auth_command(matches: &ArgMatches) -> Result<(), Error> {
+    let Some((command, command_matches)) = matches.subcommand() else {
+        unreachable!("clap requires an auth subcommand")
+    };
+    // the account can come from the config file or YUCK_ACCOUNT just like for downloads
+    let settings = load_settings(command_matches)?;
+    let account = settings
+        .account
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ACCOUNT.to_string());
+
+    match (command, command_matches) {
+        ("login", login_matches) => {
+            let credentials = settings.credentials().map_err(Error::Config)?;
+            let client = login(&credentials, flag(login_matches, "no_browser"), &account).await?;
+            match client.user_name().await {
+                Some(name) => println!(
+                    "{}",
+                    format!("Logged in to account {} as {}", account, name).green()
+                ),
+                None => println!("{}", format!("Logged in to account {}", account).green()),
+            }
+        }
+        ("logout", _) => match logout(&account) {
+            Ok(true) => println!("{}", format!("Logged out of account {}", account).green()),
+            Ok(false) => println!(
+                "{}",
+                format!("Account {} isn't logged in", account).yellow()
+            ),
+            Err(err) => {
+                return Err(Error::Io(format!(
+                    "Token of account {} couldn't be removed: {}",
+                    account, err
+                )))
+            }
+        },
+        ("status", _) => {
+            println!(
+                "{}",
+                format!("{:?}", token_cache_path(&account)).bright_yellow()
+            );
+            match cached_token(&account) {
+                Some(token) => {
+                    let expiry = token
+                        .expires_at
+                        .map(|expires_at| expires_at.to_string())
+                        .unwrap_or_else(|| "unknown".to_string());
+                    if token.is_expired() && token.refresh_token.is_none() {
+                        println!(
+                            "{}",
+                            format!("Account {}: expired at {}", account, expiry).red()
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            format!(
+                                "Account {}: logged in, token expires at {}",
+                                account, expiry
+                            )
+                            .green()
+                        );
+                    }
+                    let mut scopes: Vec<String> = token.scopes.into_iter().collect();
+                    scopes.sort();
+                    println!("Scopes: {}", scopes.join(" "));
+                }
+                None => println!("{}", format!("Account {}: not logged in", account).yellow()),
+            }
+        }
+        _ => unreachable!("clap only knows login, logout and status"),
+    }
+
+    Ok(())
+}
+
+pub fn run_config_command(matches: &ArgMatches) -> Result<(), Error> {
+    let config_file = value::<PathBuf>(matches, "config");
+
+    match matches.subcommand() {
+        Some(("path", _)) => {
+            let path = config_path(config_file.as_deref());
+            if path.exists() {
+                println!("{}", path.display());
+            } else {
+                println!("{} {}", path.display(), "(doesn't exist)".dimmed());
+            }
+        }
+        Some(("show", show_matches)) => {
+            let mut settings = load_settings(show_matches)?;
+            // the secret is only shown to be set, never printed
+            if settings.client_secret.is_some() {
+                settings.client_secret = Some("********".to_string());
+            }
+            let contents = toml::to_string(&settings).map_err(|err| {
+                Error::Config(format!("Settings couldn't be serialized: {}", err))
+            })?;
+            print!("{}", contents);
+        }
+        _ => unreachable!("clap requires a config subcommand"),
+    }
+
+    Ok(())
+}
+
+pub fn run_cache_command(matches: &ArgMatches) {
+    let cache = MatchCache::load(match_cache_path());
+
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            println!("{}", format!("{:?}", match_cache_path()).bright_yellow());
+            for (spotify_id, cached) in cache.entries() {
+                let line = format!(
+                    "{}  {}  {:.2}  {}",
+                    spotify_id, cached.url, cached.score, cached.title
+                );
+                if cached.is_expired() {
+                    println!("{} {}", line.dimmed(), "(expired)".dimmed());
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+        Some(("clear", _)) => {
+            cache.clear();
+            println!("{}", "Match cache cleared!".green());
+        }
+        _ => unreachable!("clap requires a cache subcommand"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_codec_from_its_value() {
+        let parse = per_codec(parse_cover_size);
+        assert_eq!(parse("opus=300"), Ok((Some(Codec::Opus), 300)));
+        assert_eq!(parse("m4a=600"), Ok((Some(Codec::Aac), 600)));
+        assert_eq!(parse("500"), Ok((None, 500)));
+        assert!(parse("opus=big").is_err());
+    }
+
+    #[test]
+    fn keeps_paths_with_an_equals_sign_whole() {
+        let parse = per_codec(parse_path);
+        assert_eq!(
+            parse("/music/a=b").unwrap(),
+            (None, PathBuf::from("/music/a=b"))
+        );
+        assert_eq!(
+            parse("flac=/music/a=b").unwrap(),
+            (Some(Codec::Flac), PathBuf::from("/music/a=b"))
+        );
+        assert_eq!(
+            parse("mixes=old").unwrap(),
+            (None, PathBuf::from("mixes=old"))
+        );
+    }
 }
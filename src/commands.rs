@@ -0,0 +1,420 @@
+use crate::{
+    cache::{match_cache_path, MatchCache},
+    cli::Config,
+    downloader::{format_directory, process_queue, queue_entity, retag_track, track_path},
+    error::Error,
+    export::{export_entity, ExportFormat},
+    import::read_import_file,
+    info::{print_info, print_info_json, EntityInfo},
+    input::{read_input_file, read_stdin, STDIN},
+    models::{
+        cli::Codec,
+        spotify::{Spotify, SpotifyEntity, SpotifyLink},
+    },
+    output::{is_json, Event},
+    plan::{dry_run, plan_tracks, PlannedTrack},
+    provider::{default_provider, MetadataProvider},
+    report::RunReport,
+    say,
+    search::{print_results, prompt_selection},
+};
+use clap::ArgMatches;
+use colored::Colorize;
+use std::{
+    collections::HashSet,
+    fs::{read_dir, remove_file},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+fn print_banner() {
+    say!(
+        r"__   __          _    ____                     _
+ \ \ / /   _  ___| | _|  _ \ _ __ ___ _ __ ___ (_)_   _ _ __ ___
+  \ V / | | |/ __| |/ / |_) | '__/ _ \ '_ ` _ \| | | | | '_ ` _ \
+   | || |_| | (__|   <|  __/| | |  __/ | | | | | | |_| | | | | | |
+   |_| \__,_|\___|_|\_\_|   |_|  \___|_| |_| |_|_|\__,_|_| |_| |_|
+                                                                 "
+    );
+}
+
+// links from the arguments, the input file and stdin in the order given, each only once
+fn links(matches: &ArgMatches) -> Result<Vec<SpotifyLink>, Error> {
+    let mut links: Vec<SpotifyLink> = Vec::new();
+
+    for link in matches.get_many::<String>("urls").into_iter().flatten() {
+        match link.as_str() {
+            STDIN => links.extend(read_stdin()?),
+            _ => links.push(link.parse().map_err(Error::InvalidId)?),
+        }
+    }
+    if let Some(input_file) = matches.get_one::<PathBuf>("input_file") {
+        links.extend(read_input_file(input_file)?);
+    }
+
+    let mut seen = HashSet::new();
+    links.retain(|link| seen.insert(link.to_string()));
+    Ok(links)
+}
+
+// fetches every link up front, the ones that fail are reported and left out.
+// when none of them could be fetched the run fails with the reason of the first one
+async fn resolve_all(
+    links: &[SpotifyLink],
+    provider: &mut dyn MetadataProvider,
+    report: &RunReport,
+) -> Result<Vec<(SpotifyLink, SpotifyEntity)>, Error> {
+    let mut entities = Vec::with_capacity(links.len());
+    let mut errors = Vec::new();
+
+    for link in links {
+        match provider.resolve(link).await {
+            Ok(entity) => {
+                report.emit(Event::Resolved {
+                    link: link.to_string(),
+                    name: entity.name().to_string(),
+                    tracks: entity.tracks().len(),
+                });
+                entities.push((link.clone(), entity));
+            }
+            Err(err) => {
+                report.record_failed_link(link, err.to_string());
+                errors.push(err);
+            }
+        }
+    }
+
+    let mut errors = errors.into_iter();
+    if entities.is_empty() {
+        if let Some(first) = errors.next() {
+            return Err(first);
+        }
+    }
+    for err in errors {
+        eprintln!("{}", err.to_string().red());
+    }
+    Ok(entities)
+}
+
+async fn download_entities<'a>(
+    entities: impl IntoIterator<Item = &'a SpotifyEntity>,
+    cli_args: Config,
+    report: Arc<RunReport>,
+) -> Result<(), Error> {
+    let cache = Arc::new(MatchCache::load(match_cache_path()));
+
+    if cli_args.dry_run {
+        let mut plan: Vec<PlannedTrack> = Vec::new();
+        for entity in entities {
+            let file_path = entity.directory(&cli_args.file_path);
+            plan.extend(plan_tracks(entity.tracks().to_vec(), file_path, &cli_args, &cache).await);
+        }
+        return dry_run(&plan, &cli_args);
+    }
+
+    let mut queue = Vec::new();
+    for entity in entities {
+        queue.extend(queue_entity(entity, &cli_args).await);
+    }
+    process_queue(queue, Arc::new(cli_args), report, cache).await;
+    Ok(())
+}
+
+pub async fn run_download(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_config(matches)?;
+    let links = links(matches)?;
+    print_banner();
+
+    let report = Arc::new(RunReport::default());
+    let mut provider = default_provider(&cli_args);
+    let entities = resolve_all(&links, provider.as_mut(), &report).await?;
+
+    let dry = cli_args.dry_run;
+    download_entities(
+        entities.iter().map(|(_, entity)| entity),
+        cli_args,
+        report.clone(),
+    )
+    .await?;
+
+    if !dry {
+        report.print_summary();
+    }
+    report.outcome()
+}
+
+// exported playlists need nothing from spotify, every file is read before anything is downloaded
+pub async fn run_import(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_offline_config(matches)?;
+    let report = Arc::new(RunReport::default());
+
+    let mut entities = Vec::new();
+    for path in matches.get_many::<PathBuf>("files").into_iter().flatten() {
+        let playlist = read_import_file(path)?;
+        report.emit(Event::Resolved {
+            link: path.display().to_string(),
+            name: playlist.name.clone(),
+            tracks: playlist.tracks.len(),
+        });
+        entities.push(SpotifyEntity::Playlist(playlist));
+    }
+    print_banner();
+
+    let dry = cli_args.dry_run;
+    download_entities(&entities, cli_args, report.clone()).await?;
+
+    if !dry {
+        report.print_summary();
+    }
+    report.outcome()
+}
+
+pub async fn run_search(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_config(matches)?;
+    let query = matches
+        .get_many::<String>("query")
+        .map(|words| words.cloned().collect::<Vec<String>>().join(" "))
+        .unwrap_or_default();
+    let limit = matches.get_one::<u32>("limit").copied().unwrap_or(10);
+    let kind: Spotify = matches
+        .get_one::<String>("type")
+        .map(|kind| kind.parse())
+        .transpose()
+        .map_err(Error::Config)?
+        .unwrap_or(Spotify::Track);
+
+    let mut provider = default_provider(&cli_args);
+    let results = provider.search(&query, kind, limit).await?;
+
+    if results.is_empty() {
+        say!("{}", format!("Nothing found for {}", query).yellow());
+        return Ok(());
+    }
+    print_results(&results);
+
+    let mut picked: Vec<SpotifyLink> = Vec::new();
+    for index in prompt_selection(results.len()) {
+        let result = &results[index];
+        if !matches!(result.link.kind, Spotify::Artist) {
+            picked.push(result.link.clone());
+            continue;
+        }
+
+        // an artist has no tracks of their own, one of their albums is picked next
+        let albums = provider.artist_albums(&result.link.id).await?;
+        say!(
+            "{}",
+            format!("------------{}----------", result.name).bright_yellow()
+        );
+        print_results(&albums);
+        picked.extend(
+            prompt_selection(albums.len())
+                .into_iter()
+                .map(|index| albums[index].link.clone()),
+        );
+    }
+
+    if picked.is_empty() {
+        return Ok(());
+    }
+    print_banner();
+
+    let report = Arc::new(RunReport::default());
+    let entities = resolve_all(&picked, provider.as_mut(), &report).await?;
+    download_entities(
+        entities.iter().map(|(_, entity)| entity),
+        cli_args,
+        report.clone(),
+    )
+    .await?;
+
+    report.print_summary();
+    report.outcome()
+}
+
+pub async fn run_info(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_config(matches)?;
+    let link = matches
+        .get_one::<SpotifyLink>("url")
+        .cloned()
+        .expect("clap requires a url");
+
+    let mut provider = default_provider(&cli_args);
+    let entity = provider.resolve(&link).await?;
+    let info = EntityInfo::new(&link, &entity);
+
+    if matches.get_flag("json") || is_json() {
+        print_info_json(&info)
+    } else {
+        print_info(&info);
+        Ok(())
+    }
+}
+
+// backups of albums and playlists, in every format asked for
+pub async fn run_export(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_config(matches)?;
+    let links = links(matches)?;
+    let formats: Vec<ExportFormat> = matches
+        .get_many::<ExportFormat>("format")
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect();
+
+    let report = RunReport::default();
+    let mut provider = default_provider(&cli_args);
+    let entities = resolve_all(&links, provider.as_mut(), &report).await?;
+
+    for (link, entity) in &entities {
+        if !entity.is_complete() {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} lists {} tracks, only {} of them could be exported",
+                    entity.name(),
+                    entity.number_of_songs(),
+                    entity.tracks().len()
+                )
+                .red()
+            );
+        }
+        for format in &formats {
+            let file = export_entity(entity, *format, &cli_args)?;
+            say!(
+                "{}",
+                format!("Exported {} to {}", entity.name(), file.display()).green()
+            );
+            report.emit(Event::Exported {
+                link: link.to_string(),
+                format: format.to_string(),
+                file,
+            });
+        }
+    }
+
+    report.outcome()
+}
+
+// audio files of the codec in the directory that none of the tracks would be saved as
+fn stale_files(directory: &Path, expected: &HashSet<PathBuf>, codec: Codec) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(directory) else {
+        return Vec::new();
+    };
+    let extension = codec.extension();
+
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some(extension))
+        // staging files of interrupted downloads are hidden
+        .filter(|path| {
+            !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'))
+        })
+        .filter(|path| !expected.contains(path))
+        .collect()
+}
+
+pub async fn run_sync(matches: &ArgMatches) -> Result<(), Error> {
+    let mut cli_args = Config::parse_config(matches)?;
+    cli_args.skip_existing = true;
+    let prune = matches.get_flag("prune");
+
+    let mut links = links(matches)?;
+    if links.is_empty() {
+        links.push(SpotifyLink::library());
+    }
+    print_banner();
+
+    let report = Arc::new(RunReport::default());
+    let mut provider = default_provider(&cli_args);
+    let entities = resolve_all(&links, provider.as_mut(), &report).await?;
+
+    download_entities(
+        entities.iter().map(|(_, entity)| entity),
+        cli_args.clone(),
+        report.clone(),
+    )
+    .await?;
+
+    for (link, entity) in &entities {
+        // single tracks share the path with everything else, and an artist's top tracks change
+        // over time, the files of earlier ones aren't stale
+        if matches!(entity, SpotifyEntity::Track(_) | SpotifyEntity::Artist(_)) {
+            continue;
+        }
+
+        let directory = entity.directory(&cli_args.file_path);
+        // every format is pruned in its own directory
+        let stale: Vec<PathBuf> = cli_args
+            .formats()
+            .iter()
+            .flat_map(|format| {
+                let format_args = cli_args.with_format(format);
+                let directory = format_directory(&directory, &cli_args, format);
+                let expected: HashSet<PathBuf> = entity
+                    .tracks()
+                    .iter()
+                    .map(|track| track_path(&directory, track, &format_args))
+                    .collect();
+                stale_files(&directory, &expected, format.codec)
+            })
+            .collect();
+
+        // a file is only known to be stale when every track of the link was fetched
+        let complete = entity.is_complete();
+        if prune && !complete && !stale.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "Not pruning {}: only {} of its {} tracks could be fetched",
+                    link,
+                    entity.tracks().len(),
+                    entity.number_of_songs()
+                )
+                .red()
+            );
+        }
+
+        for stale in stale {
+            if !prune || !complete {
+                say!(
+                    "{}",
+                    format!("No longer in {}: {}", link, stale.display()).yellow()
+                );
+                continue;
+            }
+            match remove_file(&stale) {
+                Ok(_) => say!("{}", format!("Removed {}", stale.display()).yellow()),
+                Err(err) => eprintln!(
+                    "{}",
+                    format!("{} couldn't be removed: {}", stale.display(), err).red()
+                ),
+            }
+        }
+    }
+
+    report.print_summary();
+    report.outcome()
+}
+
+pub async fn run_retag(matches: &ArgMatches) -> Result<(), Error> {
+    let cli_args = Config::parse_config(matches)?;
+    let links = links(matches)?;
+
+    let report = RunReport::default();
+    let mut provider = default_provider(&cli_args);
+    let entities = resolve_all(&links, provider.as_mut(), &report).await?;
+
+    for (_, entity) in &entities {
+        let directory = entity.directory(&cli_args.file_path);
+        for track in entity.tracks() {
+            retag_track(track.clone(), &cli_args, &directory, &report).await;
+        }
+    }
+
+    report.print_summary();
+    report.outcome()
+}
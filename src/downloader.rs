@@ -1,24 +1,41 @@
 use crate::{
-    cli::Config,
+    cache::MatchCache,
+    cli::{AudioFormat, Config},
+    error::Error,
+    matcher::{search_candidates, Candidate},
     metadata::{add_metadata, check_metadata},
-    models::spotify::{SpotifyAlbum, SpotifyPlaylist, SpotifyTrack},
+    models::cli::{Bitrate, Codec},
+    models::spotify::{SpotifyAlbum, SpotifyEntity, SpotifyTrack},
+    output::{is_text, Event},
+    overrides::Override,
+    report::{track_label, RunReport, TrackStatus},
+    say,
+    source::SourceAudio,
+    transcode::{encode, is_copied},
+    verify::verify_track,
 };
 use colored::Colorize;
+use futures::{stream, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::{error, info};
+use log::{error, info, warn};
 use std::{
     cmp::min,
-    fs::{create_dir_all, File},
+    collections::hash_map::DefaultHasher,
+    fs::{create_dir_all, remove_file, rename, File},
+    hash::{Hash, Hasher},
     io::Write,
     path::{Path, PathBuf},
     sync::Arc,
 };
-use tokio::task::JoinHandle;
-use youtube_dl::{SearchOptions, YoutubeDl};
+
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
 
 pub const FILTER_LETTERS: &[char] = &['/', '<', '>', '"', ' ', '(', ')'];
 
-fn create_query(spotify_song: SpotifyTrack) -> String {
+pub fn create_query(spotify_song: SpotifyTrack) -> String {
     format!(
         "{} - {:?}",
         spotify_song.name,
@@ -26,18 +43,183 @@ fn create_query(spotify_song: SpotifyTrack) -> String {
     )
 }
 
+// fills the output template in, slashes would otherwise nest the track in directories
+pub fn file_name(spotify_song: &SpotifyTrack, cli_args: &Config) -> String {
+    cli_args
+        .output_template
+        .replace("{title}", &spotify_song.name)
+        .replace("{artists}", &spotify_song.artists.join(", "))
+        .replace("{album}", &spotify_song.album_name)
+        .replace("{track_number}", &spotify_song.track_number.to_string())
+        .replace("{disc_number}", &spotify_song.disc_number.to_string())
+        .replace(['/', '\\'], "-")
+}
+
+// final location of a track once it has been downloaded, tagged and verified
+pub fn track_path(file_path: &Path, spotify_song: &SpotifyTrack, cli_args: &Config) -> PathBuf {
+    file_path.join(format!(
+        "{}.{}",
+        file_name(spotify_song, cli_args),
+        cli_args.codec.extension()
+    ))
+}
+
+// the directory of a track in another format, the same one below the root of that format
+pub fn format_directory(file_path: &Path, cli_args: &Config, format: &AudioFormat) -> PathBuf {
+    match file_path.strip_prefix(&cli_args.file_path) {
+        Ok(relative) => format.file_path.join(relative),
+        Err(_) => file_path.to_path_buf(),
+    }
+}
+
+// the file of the track in every format, the codec of the config first
+pub fn format_paths(
+    file_path: &Path,
+    spotify_song: &SpotifyTrack,
+    cli_args: &Config,
+) -> Vec<(AudioFormat, PathBuf)> {
+    cli_args
+        .formats()
+        .into_iter()
+        .map(|format| {
+            let directory = format_directory(file_path, cli_args, &format);
+            let path = track_path(&directory, spotify_song, &cli_args.with_format(&format));
+            (format, path)
+        })
+        .collect()
+}
+
+// tells the temporary files of tracks apart whose file names are the same, e.g. two "Intro"s of
+// one queue downloading at the same time. imported tracks may have no spotify id
+fn temp_key(spotify_song: &SpotifyTrack) -> String {
+    if !spotify_song.id.is_empty() {
+        return spotify_song.id.clone();
+    }
+    let mut hasher = DefaultHasher::new();
+    (
+        &spotify_song.name,
+        &spotify_song.artists,
+        &spotify_song.album_name,
+        spotify_song.disc_number,
+        spotify_song.track_number,
+    )
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+// hidden file in the same directory the track is downloaded and tagged into, so that a crash
+// never leaves a half written file under the final name
+fn staging_path(file_path: &Path, spotify_song: &SpotifyTrack, cli_args: &Config) -> PathBuf {
+    file_path.join(format!(
+        ".{}.{}.tmp.{}",
+        file_name(spotify_song, cli_args),
+        temp_key(spotify_song),
+        cli_args.codec.extension()
+    ))
+}
+
+// where the source's audio is fetched to before it is transcoded, the source adds the extension
+fn raw_path(file_path: &Path, spotify_song: &SpotifyTrack, cli_args: &Config) -> PathBuf {
+    file_path.join(format!(
+        ".{}.{}.source",
+        file_name(spotify_song, cli_args),
+        temp_key(spotify_song)
+    ))
+}
+
+// audio a source downloaded is gone once every format is written, library files stay
+fn discard_source(audio: &SourceAudio) {
+    if audio.temporary {
+        remove_staged_file(&audio.path);
+    }
+}
+
+fn remove_staged_file(staging: &Path) {
+    if staging.exists() {
+        if let Err(err) = remove_file(staging) {
+            error!("Staging file {:?} couldn't be removed: {}", staging, err);
+        }
+    }
+}
+
+// rename is atomic within the same directory, readers either see the previous file or the complete one
+fn commit_staged_file(staging: &Path, target: &Path) -> Result<(), Error> {
+    match rename(staging, target) {
+        Ok(_) => {
+            info!("Moved {:?} into place at {:?}", staging, target);
+            Ok(())
+        }
+        Err(err) => {
+            remove_staged_file(staging);
+            Err(Error::Io(format!(
+                "{:?} couldn't be moved to {:?}: {}",
+                staging, target, err
+            )))
+        }
+    }
+}
+
+// tags the staging file and only moves it into place once the tags read back
+fn tag_staged_file(
+    song: SpotifyTrack,
+    image_dir: PathBuf,
+    staging: PathBuf,
+    target: &Path,
+    cover_size: Option<u32>,
+) -> Result<(), Error> {
+    let tagged = add_metadata(song, image_dir, staging.clone(), cover_size)
+        .and_then(|_| check_metadata(&staging));
+    if let Err(err) = tagged {
+        remove_staged_file(&staging);
+        return Err(err);
+    }
+    commit_staged_file(&staging, target)
+}
+
+// with --link a library file that would only be copied is linked instead. the link keeps the tags
+// of the library, tagging it would write into the library file
+fn library_link(
+    audio: &SourceAudio,
+    codec: Codec,
+    bitrate: Bitrate,
+    link: bool,
+) -> Option<PathBuf> {
+    (link && !audio.temporary && is_copied(&audio.path, codec, bitrate)).then(|| audio.path.clone())
+}
+
+// the link takes the place of the verified staging file and is moved into place the same way
+fn link_staged_file(source: &Path, staging: PathBuf, target: &Path) -> Result<(), Error> {
+    remove_staged_file(&staging);
+    symlink(source, &staging).map_err(|err| {
+        Error::Io(format!(
+            "{:?} couldn't be linked to {:?}: {}",
+            staging, source, err
+        ))
+    })?;
+    info!("Linked {:?} to the library file {:?}", target, source);
+    commit_staged_file(&staging, target)
+}
+
+// fetches the candidate and encodes it into the staging file of the config's codec. the fetched
+// audio is returned for the extra formats, the caller discards it
 pub async fn download_singular_track(
     spotify_song: SpotifyTrack,
+    candidate: &Candidate,
     cli_args: Arc<Config>,
     file_path: PathBuf,
-) -> bool {
-    let file_format = format!("{}.{}", spotify_song.name, cli_args.codec);
+) -> Result<SourceAudio, Error> {
+    let staging = staging_path(&file_path, &spotify_song, &cli_args);
     let bar = (
         format!("{} - {}", spotify_song.name, spotify_song.artists.join(",")),
         "█▓▒",
         "green",
     );
-    let pb = ProgressBar::new(cli_args.chunk.unwrap().into());
+    let total = u64::from(cli_args.chunk.unwrap_or(1));
+    let pb = if !is_text() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(total)
+    };
     let mut downloaded: u64 = 0;
 
     pb.set_style(
@@ -47,46 +229,50 @@ pub async fn download_singular_track(
     );
     pb.set_prefix(bar.0);
 
-    let _ = match cli_args.file_path.parent() {
-        None => {
-            let msg = format!("No parent directory found!").red();
-            error!("{}", msg);
-            return false;
-        }
-        Some(v) => v,
-    };
+    if cli_args.file_path.parent().is_none() {
+        return Err(Error::Io(format!(
+            "{:?} has no parent directory",
+            cli_args.file_path
+        )));
+    }
 
-    let query = create_query(spotify_song.clone());
+    remove_staged_file(&staging);
+    // ffmpeg doesn't create the directory of the file it writes
+    create_dir_all(&file_path).map_err(|err| {
+        Error::Io(format!(
+            "Directory {:?} couldn't be created: {}",
+            file_path, err
+        ))
+    })?;
 
-    let mut yt_client = YoutubeDl::search_for(&SearchOptions::youtube(query));
-    println!(
+    say!(
         "{}",
         format!(
-            "\nFetching {} - {}\n",
+            "\nFetching {} - {} from {}\n",
             spotify_song.name,
-            spotify_song.artists.join(",")
+            spotify_song.artists.join(","),
+            candidate.url
         )
         .cyan()
     );
 
-    if let Ok(_) = yt_client
-        .extract_audio(true)
-        .output_template(file_format.clone())
-        .extra_arg("--audio-format")
-        .extra_arg(cli_args.codec.to_string())
-        .extra_arg("--audio-quality")
-        .extra_arg(cli_args.bitrate.to_string())
-        .download_to_async(file_path)
-        .await
-    {
-        while downloaded < cli_args.chunk.unwrap().into() {
-            downloaded = min(downloaded + 1, cli_args.chunk.unwrap().into());
-            pb.set_position(downloaded);
-        }
+    // the source's audio is fetched as it is and transcoded once per format, so every codec is
+    // written the same way
+    let raw = raw_path(&file_path, &spotify_song, &cli_args);
+    let audio = cli_args.source.download(candidate, &raw).await?;
+    if let Err(err) = encode(&audio.path, &staging, cli_args.codec, cli_args.bitrate).await {
+        discard_source(&audio);
+        remove_staged_file(&staging);
+        return Err(err);
+    }
+
+    while downloaded < total {
+        downloaded = min(downloaded + 1, total);
+        pb.set_position(downloaded);
     }
     pb.finish_with_message("downloaded!");
 
-    println!(
+    say!(
         "{}",
         format!(
             "\n{} - {} downloaded!\n",
@@ -96,249 +282,442 @@ pub async fn download_singular_track(
         .green()
     );
 
-    true
+    Ok(audio)
 }
 
-pub async fn process_track_download(spotify_song: SpotifyTrack, cli_args: &mut Config) {
-    let file_format = format!("{}.{}", spotify_song.name, cli_args.codec);
-    cli_args.file_path.push(file_format);
-    let cli_config = Arc::new(cli_args.clone());
-    let file_path = cli_args.clone().file_path.clone();
-    if download_singular_track(spotify_song.clone(), cli_config, file_path).await {
-        cli_args.file_path.pop();
-        let file_path = &cli_args.clone().file_path;
-        let dir = format!("{}.jpeg", spotify_song.name);
-        cli_args.clone().file_path.push(dir);
-        let song = &spotify_song;
-
-        download_playlist_songs_art(file_path.to_path_buf(), song.to_owned()).await;
-
-        let mut image_dir = file_path.clone();
-        let mut song_dir = file_path.clone();
-        let song_name = filter_image_name(&song);
-        let song_file = format!("{}.{}", song.name, cli_args.codec);
-        let image_file = format!("{}.jpeg", song_name);
-        image_dir.push(image_file);
-        song_dir.push(song_file);
-
-        add_metadata(song.clone(), image_dir.to_path_buf(), song_dir.clone());
-
-        check_metadata(&song_dir);
-        image_dir.pop();
-        song_dir.pop();
+// downloads candidates into the staging file from the best match down and returns the first one
+// that passes verification with its fetched audio, or the reasons every candidate was rejected
+async fn try_candidates(
+    spotify_song: &SpotifyTrack,
+    candidates: &[Candidate],
+    cli_args: Arc<Config>,
+    file_path: &Path,
+) -> Result<(Candidate, SourceAudio), Vec<String>> {
+    let staging = staging_path(file_path, spotify_song, &cli_args);
+    let mut rejections: Vec<String> = Vec::new();
+
+    for candidate in candidates {
+        let audio = match download_singular_track(
+            spotify_song.clone(),
+            candidate,
+            cli_args.clone(),
+            file_path.to_path_buf(),
+        )
+        .await
+        {
+            Ok(audio) => audio,
+            Err(err) => {
+                error!("{}", err);
+                rejections.push(format!("{}: {}", candidate.id, err));
+                continue;
+            }
+        };
+
+        match verify_track(&staging, spotify_song, candidate, &cli_args).await {
+            Ok(_) => return Ok((candidate.clone(), audio)),
+            Err(reason) => {
+                warn!(
+                    "{}",
+                    format!(
+                        "Rejected {} for {}: {}",
+                        candidate.url, spotify_song.name, reason
+                    )
+                    .yellow()
+                );
+                rejections.push(format!("{}: {}", candidate.id, reason));
+                remove_staged_file(&staging);
+                discard_source(&audio);
+            }
+        }
     }
+
+    Err(rejections)
 }
 
-pub async fn download_album_songs(
-    songs: Vec<SpotifyTrack>,
+async fn search_and_try(
+    spotify_song: &SpotifyTrack,
     cli_args: Arc<Config>,
-    file_path: PathBuf,
-) -> bool {
-    for song in songs.into_iter() {
-        let track = &song;
-        if download_singular_track(track.to_owned(), cli_args.clone(), file_path.clone()).await {
-            let mut image_dir = file_path.clone();
-            let mut song_dir = file_path.clone();
-            let song_name = filter_image_name(&song);
-            let song_file = format!("{}.{}", song.name, cli_args.codec);
-            let image_file = format!("{}.jpeg", song_name);
-            image_dir.push(image_file);
-            song_dir.push(song_file);
-            add_metadata(song.clone(), image_dir.to_path_buf(), song_dir.clone());
-            check_metadata(&song_dir);
-            image_dir.pop();
-            song_dir.pop();
+    file_path: &Path,
+) -> Result<(Candidate, SourceAudio), Vec<String>> {
+    let query = create_query(spotify_song.clone());
+    let candidates = search_candidates(cli_args.source.as_ref(), query.clone(), spotify_song).await;
+    let mut rejections =
+        match try_candidates(spotify_song, &candidates, cli_args.clone(), file_path).await {
+            Ok(chosen) => return Ok(chosen),
+            Err(rejections) => rejections,
+        };
+
+    // the library had the track but none of its files passed, the fallback source still may
+    let all_local = !candidates.is_empty()
+        && candidates
+            .iter()
+            .all(|candidate| candidate.url.starts_with("file://"));
+    if let Some(fallback) = cli_args.source.fallback().filter(|_| all_local) {
+        info!(
+            "No file of the library passed for {}, searching {}",
+            spotify_song.name,
+            fallback.name()
+        );
+        let candidates = search_candidates(fallback, query, spotify_song).await;
+        match try_candidates(spotify_song, &candidates, cli_args, file_path).await {
+            Ok(chosen) => return Ok(chosen),
+            Err(fallback_rejections) => rejections.extend(fallback_rejections),
         }
     }
-    true
+    Err(rejections)
 }
 
-pub async fn download_album_art(
-    album_art_dir: &Path,
-    link: Option<String>,
-    spotify_album: &SpotifyAlbum,
-) {
-    let mut directory = album_art_dir.to_owned();
-
-    if link.is_some() {
-        let response = reqwest::get(link.unwrap()).await.unwrap();
-        let image = response.bytes().await.expect("Data couldn't be read!");
-
-        directory.push(spotify_album.name.clone());
-
-        let name: String = spotify_album
-            .name
-            .chars()
-            .filter(|c| !FILTER_LETTERS.contains(c))
-            .collect();
-
-        let img_format = format!("{}.jpeg", name);
-        directory.push(img_format);
-        if let Some(parent) = directory.parent() {
-            if !parent.exists() {
-                match create_dir_all(parent) {
-                    Ok(_) => info!("Directory created successfully!"),
-                    Err(err) => {
-                        error!("Directory couldn't be created: {:?}", err);
-                        return;
+// downloads the track and its cover into the staging file, tags it and only then renames it into place,
+// then does the same for every extra format from the same download.
+// a file of the library is tried first, then a cached match, then the search results from the
+// best match down
+pub async fn download_and_tag_track(
+    spotify_song: SpotifyTrack,
+    cli_args: Arc<Config>,
+    file_path: PathBuf,
+    report: Arc<RunReport>,
+    cache: Arc<MatchCache>,
+) -> bool {
+    if cli_args.skip_existing
+        && format_paths(&file_path, &spotify_song, &cli_args)
+            .iter()
+            .all(|(_, path)| path.exists())
+    {
+        report.record(&spotify_song, TrackStatus::UpToDate);
+        return true;
+    }
+
+    let cached = if cli_args.rematch {
+        None
+    } else {
+        cache.get(&spotify_song.id)
+    };
+    let cached_url = cached.as_ref().map(|cached| cached.url.clone());
+
+    let mut rejections: Vec<String> = Vec::new();
+    let chosen = match cli_args.overrides.get(&spotify_song.id) {
+        Some(Override::Skip) => {
+            info!(
+                "Skipping {} as set in the overrides file",
+                spotify_song.name
+            );
+            report.record(
+                &spotify_song,
+                TrackStatus::Skipped("Skipped by override".to_string()),
+            );
+            return false;
+        }
+        Some(Override::Url(url)) => {
+            let candidates = vec![Candidate::pinned(url.clone())];
+            try_candidates(&spotify_song, &candidates, cli_args.clone(), &file_path).await
+        }
+        None => {
+            // a track found in the library is taken from there, even when a download of it was
+            // cached before the library was set
+            let in_library = !cli_args
+                .source
+                .local_candidates(&spotify_song)
+                .await
+                .is_empty();
+            if let Some(cached) = cached.filter(|_| !in_library) {
+                info!(
+                    "Reusing the cached match {} for {}",
+                    cached.url, spotify_song.name
+                );
+                let candidates = vec![cached.to_candidate()];
+                match try_candidates(&spotify_song, &candidates, cli_args.clone(), &file_path).await
+                {
+                    Ok(candidate) => Ok(candidate),
+                    Err(cache_rejections) => {
+                        rejections = cache_rejections;
+                        search_and_try(&spotify_song, cli_args.clone(), &file_path).await
                     }
                 }
             } else {
-                info!("Directory already exists");
+                search_and_try(&spotify_song, cli_args.clone(), &file_path).await
             }
         }
+    };
 
-        match File::create(directory) {
-            Ok(mut file) => {
-                file.write_all(&image).unwrap();
-            }
-            Err(err) => error!("File couldn't be created: {:?}", err),
+    let (candidate, audio) = match chosen {
+        Ok(chosen) => chosen,
+        Err(search_rejections) => {
+            rejections.extend(search_rejections);
+            let status = if rejections.is_empty() {
+                TrackStatus::Failed("No search results".to_string())
+            } else {
+                TrackStatus::Flagged(rejections.join("; "))
+            };
+            report.record(&spotify_song, status);
+            return false;
         }
-    }
-}
+    };
 
-pub async fn process_album_download(spotify_album: SpotifyAlbum, cli_args: &mut Config) -> bool {
-    let mut parallel_downloads = if cli_args.chunk.is_some() {
-        cli_args.chunk.unwrap()
+    let matched_by = if candidate.pinned {
+        "override"
+    } else if cached_url.as_ref() == Some(&candidate.url) {
+        "cache"
     } else {
-        10
+        "search"
     };
-
-    download_album_art(
-        &cli_args.file_path,
-        spotify_album.cover_url.clone(),
-        &spotify_album,
-    )
-    .await;
-
-    cli_args.file_path.push(spotify_album.name.clone());
-    let file_path = cli_args.file_path.clone();
-
-    let cli_args = Arc::new(cli_args.clone());
-    let no_of_songs = spotify_album.number_of_songs;
-    let total_tasks = no_of_songs.div_ceil(parallel_downloads);
-
-    if no_of_songs < parallel_downloads {
-        parallel_downloads = no_of_songs;
+    report.emit(Event::Matched {
+        spotify_id: spotify_song.id.clone(),
+        track: track_label(&spotify_song),
+        matched_by: matched_by.to_string(),
+        source: candidate.url.clone(),
+        title: candidate.title.clone(),
+        score: candidate.score,
+    });
+    report.emit(Event::Downloaded {
+        spotify_id: spotify_song.id.clone(),
+        track: track_label(&spotify_song),
+        source: candidate.url.clone(),
+    });
+
+    let staging = staging_path(&file_path, &spotify_song, &cli_args);
+    if let Err(err) = download_playlist_songs_art(file_path.clone(), spotify_song.clone()).await {
+        warn!("{}", err);
     }
 
-    // cli_args.chunk = Some(parallel_downloads);
+    let target = track_path(&file_path, &spotify_song, &cli_args);
+    let image_dir = file_path.join(format!("{}.jpeg", filter_image_name(&spotify_song)));
+    let song = spotify_song.clone();
+    let committed_target = target.clone();
+    let cover_size = cli_args.cover_size;
+    let link = library_link(&audio, cli_args.codec, cli_args.bitrate, cli_args.link);
+
+    let committed = tokio::task::spawn_blocking(move || {
+        match link {
+            Some(source) => link_staged_file(&source, staging, &committed_target),
+            None => tag_staged_file(
+                song,
+                image_dir.clone(),
+                staging,
+                &committed_target,
+                cover_size,
+            ),
+        }
+        .map(|_| image_dir)
+    })
+    .await
+    .unwrap_or_else(|err| Err(Error::Tagging(format!("Tagging task failed: {}", err))));
+
+    let written = match committed {
+        Ok(image_dir) => {
+            // library files are found again by their tags, caching them would outlive a moved file
+            if !candidate.pinned && !candidate.url.starts_with("file://") {
+                cache.insert(&spotify_song.id, &candidate);
+            }
+            report.emit(Event::Tagged {
+                spotify_id: spotify_song.id.clone(),
+                track: track_label(&spotify_song),
+                file: target,
+            });
+            write_extra_formats(
+                &spotify_song,
+                &candidate,
+                &audio,
+                &cli_args,
+                &file_path,
+                image_dir,
+                &report,
+            )
+            .await
+        }
+        Err(err) => Err(err),
+    };
+    discard_source(&audio);
 
-    let mut chunk: Vec<SpotifyTrack>;
-    let mut handles = Vec::with_capacity(total_tasks as usize);
+    match written {
+        Ok(_) => {
+            report.record(&spotify_song, TrackStatus::Downloaded);
+            true
+        }
+        Err(err) => {
+            error!("{}", err);
+            report.record(&spotify_song, TrackStatus::Failed(err.to_string()));
+            false
+        }
+    }
+}
 
-    println!(
-        "{}",
-        format!("Launching Album: {}", &spotify_album.name).bright_yellow()
-    );
-    let ascii_separator = r#"
-_________________________________________
-"#;
-    println!("{}", ascii_separator);
-
-    for songs in spotify_album.tracks.chunks(parallel_downloads as usize) {
-        chunk = songs.to_vec();
-        let handle = tokio::spawn(download_album_songs(
-            chunk,
-            cli_args.clone(),
-            file_path.clone(),
-        ));
+// every extra format is encoded from the audio the config's codec was, then verified, tagged with
+// its own cover size and moved into place like it
+async fn write_extra_formats(
+    spotify_song: &SpotifyTrack,
+    candidate: &Candidate,
+    audio: &SourceAudio,
+    cli_args: &Config,
+    file_path: &Path,
+    image_dir: PathBuf,
+    report: &RunReport,
+) -> Result<(), Error> {
+    for format in &cli_args.extra_formats {
+        let format_args = cli_args.with_format(format);
+        let directory = format_directory(file_path, cli_args, format);
+        let staging = staging_path(&directory, spotify_song, &format_args);
+        let target = track_path(&directory, spotify_song, &format_args);
+
+        create_dir_all(&directory).map_err(|err| {
+            Error::Io(format!(
+                "Directory {:?} couldn't be created: {}",
+                directory, err
+            ))
+        })?;
+        remove_staged_file(&staging);
+        say!(
+            "{}",
+            format!("Writing {} as {}", spotify_song.name, format.codec).cyan()
+        );
+
+        if let Err(err) = encode(&audio.path, &staging, format.codec, format.bitrate).await {
+            remove_staged_file(&staging);
+            return Err(err);
+        }
+        if let Err(reason) = verify_track(&staging, spotify_song, candidate, &format_args).await {
+            remove_staged_file(&staging);
+            return Err(Error::Download(format!(
+                "{} as {} was rejected: {}",
+                spotify_song.name, format.codec, reason
+            )));
+        }
 
-        handles.push(handle);
-    }
+        let song = spotify_song.clone();
+        let image_dir = image_dir.clone();
+        let committed_target = target.clone();
+        let cover_size = format.cover_size;
+        let link = library_link(audio, format.codec, format.bitrate, cli_args.link);
+        tokio::task::spawn_blocking(move || match link {
+            Some(source) => link_staged_file(&source, staging, &committed_target),
+            None => tag_staged_file(song, image_dir, staging, &committed_target, cover_size),
+        })
+        .await
+        .unwrap_or_else(|err| Err(Error::Tagging(format!("Tagging task failed: {}", err))))?;
 
-    for handle in handles {
-        handle.await.unwrap();
+        report.emit(Event::Tagged {
+            spotify_id: spotify_song.id.clone(),
+            track: track_label(spotify_song),
+            file: target,
+        });
     }
-
-    true
+    Ok(())
 }
 
-pub async fn download_playlist_songs(
-    songs: Vec<SpotifyTrack>,
-    cli_args: Arc<Config>,
-    file_path: PathBuf,
+// writes the tags and cover into the files of every format that were downloaded before, e.g. after
+// spotify fixed its metadata
+pub async fn retag_track(
+    spotify_song: SpotifyTrack,
+    cli_args: &Config,
+    file_path: &Path,
+    report: &RunReport,
 ) -> bool {
-    for song in &songs {
-        tokio::spawn(download_playlist_songs_art(file_path.clone(), song.clone()))
-            .await
-            .unwrap();
+    let targets: Vec<(AudioFormat, PathBuf)> = format_paths(file_path, &spotify_song, cli_args)
+        .into_iter()
+        .filter(|(_, target)| target.exists())
+        .collect();
+    if targets.is_empty() {
+        report.record(
+            &spotify_song,
+            TrackStatus::Skipped("Not downloaded".to_string()),
+        );
+        return false;
+    }
 
-        let response = match tokio::spawn(download_singular_track(
-            song.clone(),
-            cli_args.clone(),
-            file_path.clone(),
-        ))
+    if let Err(err) =
+        download_playlist_songs_art(file_path.to_path_buf(), spotify_song.clone()).await
+    {
+        warn!("{}", err);
+    }
+    let image_dir = file_path.join(format!("{}.jpeg", filter_image_name(&spotify_song)));
+
+    for (format, target) in targets {
+        // a link into the library keeps the library's tags
+        if target.is_symlink() {
+            info!("{:?} is linked to the library, it isn't retagged", target);
+            continue;
+        }
+        let song = spotify_song.clone();
+        let image_dir = image_dir.clone();
+        let tagged_target = target.clone();
+        let tagged = tokio::task::spawn_blocking(move || {
+            add_metadata(song, image_dir, tagged_target.clone(), format.cover_size)
+                .and_then(|_| check_metadata(&tagged_target))
+        })
         .await
-        {
-            Ok(b) => b,
-            Err(err) => {
-                error!("Error occured while downloading a song: {}", err);
-                return false;
-            }
-        };
+        .unwrap_or_else(|err| Err(Error::Tagging(format!("Tagging task failed: {}", err))));
 
-        if response {
-            tokio::task::block_in_place(|| {
-                let mut image_dir = file_path.clone();
-                let mut song_dir = file_path.clone();
-                let song_name = filter_image_name(&song);
-                let song_file = format!("{}.{}", song.name, cli_args.codec);
-                let image_file = format!("{}.jpeg", song_name);
-                image_dir.push(image_file);
-                song_dir.push(song_file);
-                add_metadata(song.clone(), image_dir.to_path_buf(), song_dir.clone());
-                check_metadata(&song_dir);
-                image_dir.pop();
-                song_dir.pop();
-            });
+        if let Err(err) = tagged {
+            error!("{}", err);
+            report.record(&spotify_song, TrackStatus::Failed(err.to_string()));
+            return false;
         }
+        report.emit(Event::Tagged {
+            spotify_id: spotify_song.id.clone(),
+            track: track_label(&spotify_song),
+            file: target,
+        });
     }
-
+    report.record(&spotify_song, TrackStatus::Retagged);
     true
 }
 
-pub async fn download_playlist_songs_art(album_art_dir: PathBuf, song: SpotifyTrack) {
-    let mut dir = album_art_dir.to_owned();
+// the response body of an image, covers are small enough to be held in memory
+async fn fetch_image(link: &str) -> Result<Vec<u8>, Error> {
+    let response = reqwest::get(link)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| Error::Network(format!("Cover {} couldn't be fetched: {}", link, err)))?;
+    let image = response
+        .bytes()
+        .await
+        .map_err(|err| Error::Network(format!("Cover {} couldn't be read: {}", link, err)))?;
+    Ok(image.to_vec())
+}
 
-    let image = reqwest::get(song.album_cover.clone()).await.unwrap();
-    if let Some(parent) = album_art_dir.parent() {
-        if !parent.exists() {
-            match create_dir_all(parent) {
-                Ok(_) => info!(
-                    "{}",
-                    format!("\nDirectory {:?} created successfully!\n", parent)
-                ),
-                Err(err) => {
-                    let msg = format!(
-                        "\nError while creating a directory {:?}: {:?}\n",
-                        parent, err
-                    )
-                    .red();
-                    error!("{}", msg);
-                }
-            }
-        } else {
-            info!(
-                "{}",
-                format!("\nDirectory {:?} already exists!\n", parent).bright_red()
-            );
-        }
+fn write_image(path: &Path, image: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|err| {
+            Error::Io(format!(
+                "Directory {:?} couldn't be created: {}",
+                parent, err
+            ))
+        })?;
     }
+    File::create(path)
+        .and_then(|mut file| file.write_all(image))
+        .map_err(|err| Error::Io(format!("Cover {:?} couldn't be written: {}", path, err)))
+}
 
-    let song_name = filter_image_name(&song);
-    let file_format = format!("{}.jpeg", song_name);
-    dir.push(file_format);
-
-    let mut image_file = match File::create(dir.clone()) {
-        Ok(file) => file,
-        Err(err) => {
-            error!("Error while creating a file: {:?}", err);
-            return;
-        }
+pub async fn download_album_art(
+    album_art_dir: &Path,
+    link: Option<String>,
+    spotify_album: &SpotifyAlbum,
+) -> Result<(), Error> {
+    let Some(link) = link else {
+        return Ok(());
     };
-    image_file.write_all(&image.bytes().await.unwrap()).unwrap();
-    dir.pop();
+    let image = fetch_image(&link).await?;
+
+    let name: String = spotify_album
+        .name
+        .chars()
+        .filter(|c| !FILTER_LETTERS.contains(c))
+        .collect();
+    let directory = album_art_dir
+        .join(&spotify_album.name)
+        .join(format!("{}.jpeg", name));
+
+    write_image(&directory, &image)
+}
+
+// the cover of the track's album next to it, read back while tagging
+pub async fn download_playlist_songs_art(
+    album_art_dir: PathBuf,
+    song: SpotifyTrack,
+) -> Result<(), Error> {
+    let image = fetch_image(&song.album_cover).await?;
+    let path = album_art_dir.join(format!("{}.jpeg", filter_image_name(&song)));
+    write_image(&path, &image)
 }
 
 fn filter_image_name(song: &SpotifyTrack) -> String {
@@ -348,50 +727,131 @@ fn filter_image_name(song: &SpotifyTrack) -> String {
         .collect::<String>()
 }
 
-pub async fn process_playlist_download(spotify_playlist: SpotifyPlaylist, cli_args: &mut Config) {
-    if spotify_playlist.number_of_songs == 0 {
-        let msg = format!("There's no song to download!\n").red();
-        error!("{}", msg);
-        return;
+// a track waiting in the download queue, with the directory of the album or playlist it came from
+#[derive(Clone, Debug)]
+pub struct QueuedTrack {
+    pub track: SpotifyTrack,
+    pub file_path: PathBuf,
+}
+
+// the tracks of a resolved link, the album cover is downloaded next to them right away
+pub async fn queue_entity(entity: &SpotifyEntity, cli_args: &Config) -> Vec<QueuedTrack> {
+    let file_path = entity.directory(&cli_args.file_path);
+
+    match entity {
+        SpotifyEntity::Album(album) => {
+            if let Err(err) =
+                download_album_art(&cli_args.file_path, album.cover_url.clone(), album).await
+            {
+                warn!("{}", err);
+            }
+            say!(
+                "{}",
+                format!(
+                    "Queued Album: {} ({} tracks)",
+                    album.name, album.number_of_songs
+                )
+                .bright_yellow()
+            );
+        }
+        SpotifyEntity::Playlist(playlist) if playlist.tracks.is_empty() => {
+            let msg = format!("There's no song to download in {}!", playlist.name).red();
+            error!("{}", msg);
+        }
+        SpotifyEntity::Playlist(playlist) => say!(
+            "{}",
+            format!(
+                "Queued Playlist: {} ({} tracks)",
+                playlist.name, playlist.number_of_songs
+            )
+            .bright_yellow()
+        ),
+        SpotifyEntity::Track(track) => say!(
+            "{}",
+            format!("Queued Track: {}", track.name).bright_yellow()
+        ),
+        SpotifyEntity::Artist(artist) => say!(
+            "{}",
+            format!(
+                "Queued Top Tracks: {} ({} tracks)",
+                artist.name,
+                artist.top_tracks.len()
+            )
+            .bright_yellow()
+        ),
     }
 
-    cli_args.file_path.push(spotify_playlist.name.clone());
-    let file_path = cli_args.file_path.clone();
+    entity
+        .tracks()
+        .iter()
+        .map(|track| QueuedTrack {
+            track: track.clone(),
+            file_path: file_path.clone(),
+        })
+        .collect()
+}
 
-    let parallel_downloads = if let Some(chunk) = cli_args.chunk {
-        chunk
+// downloads the tracks of every link in one queue, at most chunk of them at a time
+pub async fn process_queue(
+    queue: Vec<QueuedTrack>,
+    cli_args: Arc<Config>,
+    report: Arc<RunReport>,
+    cache: Arc<MatchCache>,
+) {
+    let parallel_downloads = cli_args.chunk.unwrap_or(10).max(1) as usize;
+    let total = queue.len();
+
+    for queued in &queue {
+        report.emit(Event::Queued {
+            spotify_id: queued.track.id.clone(),
+            track: track_label(&queued.track),
+            file: track_path(&queued.file_path, &queued.track, &cli_args),
+        });
+    }
+
+    let progress = if !is_text() {
+        ProgressBar::hidden()
     } else {
-        10
+        ProgressBar::new(total as u64)
     };
-    cli_args.chunk = Some(parallel_downloads);
-
-    let number_of_songs = spotify_playlist.number_of_songs;
-    let no_of_tasks = number_of_songs.div_ceil(parallel_downloads);
-    let mut chunk_of_songs: Vec<SpotifyTrack>;
-    let mut handles: Vec<JoinHandle<bool>> = Vec::with_capacity(no_of_tasks as usize);
-    let cli_args = Arc::new(cli_args.clone());
-
-    println!(
-        "{}",
-        format!("Launching Playlist: {}", &spotify_playlist.name).bright_yellow()
+    progress.set_style(
+        ProgressStyle::with_template("{prefix:.bold} [{pos}/{len}] |{bar:40.green}| {msg}")
+            .unwrap()
+            .progress_chars("█▓▒"),
     );
-    let ascii_separator = r#"
-_________________________________________
-"#;
-    println!("{}", ascii_separator);
-
-    for songs in spotify_playlist.tracks.chunks(parallel_downloads as usize) {
-        chunk_of_songs = songs.to_vec();
-
-        let handle = tokio::spawn(download_playlist_songs(
-            chunk_of_songs,
-            cli_args.clone(),
-            file_path.clone(),
-        ));
-        handles.push(handle);
-    }
+    progress.set_prefix("Total");
+
+    stream::iter(queue)
+        .map(|queued| {
+            // the track stays here, a task that panicked never recorded it in the report
+            let track = queued.track.clone();
+            let handle = tokio::spawn(download_and_tag_track(
+                queued.track,
+                cli_args.clone(),
+                queued.file_path,
+                report.clone(),
+                cache.clone(),
+            ));
+            async move { (track, handle.await) }
+        })
+        .buffer_unordered(parallel_downloads)
+        .for_each(|(track, result)| {
+            if let Err(err) = result {
+                error!("Error occured while downloading {}: {}", track.name, err);
+                report.record(
+                    &track,
+                    TrackStatus::Failed(format!("Download task failed: {}", err)),
+                );
+            }
+            progress.inc(1);
+            progress.set_message(track.name);
+            report.emit(Event::Progress {
+                completed: progress.position() as usize,
+                total,
+            });
+            async {}
+        })
+        .await;
 
-    for handle in handles {
-        handle.await.unwrap();
-    }
+    progress.finish_with_message("done");
 }
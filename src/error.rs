@@ -0,0 +1,155 @@
+use rspotify::{http::HttpError, ClientError};
+use std::fmt;
+
+// everything a run can fail with, every kind exits with its own code
+#[derive(Debug)]
+pub enum Error {
+    // the config file, the environment or the arguments can't be used
+    Config(String),
+    // spotify refused the credentials or the user's authorization
+    Auth(String),
+    // a link or id that isn't one spotify knows the format of
+    InvalidId(String),
+    NotFound(String),
+    RateLimited(String),
+    Network(String),
+    Search(String),
+    Download(String),
+    Tagging(String),
+    Io(String),
+    // the run went through, but some of the links or tracks failed
+    PartialFailure { failed: usize, total: usize },
+}
+
+impl Error {
+    // 2 is what clap exits with for invalid arguments
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::PartialFailure { .. } => 1,
+            Error::Config(_) => 2,
+            Error::Auth(_) => 3,
+            Error::InvalidId(_) => 4,
+            Error::NotFound(_) => 5,
+            Error::RateLimited(_) => 6,
+            Error::Network(_) => 7,
+            Error::Search(_) => 8,
+            Error::Download(_) => 9,
+            Error::Tagging(_) => 10,
+            Error::Io(_) => 11,
+        }
+    }
+
+    // sorts a failed spotify request by the status code spotify answered with
+    pub fn spotify(context: &str, err: ClientError) -> Error {
+        let message = format!("{}: {}", context, err);
+        match err {
+            ClientError::Http(http) => match *http {
+                HttpError::StatusCode(response) => match response.status().as_u16() {
+                    400 => Error::InvalidId(message),
+                    401 | 403 => Error::Auth(message),
+                    404 => Error::NotFound(message),
+                    429 => Error::RateLimited(message),
+                    _ => Error::Network(message),
+                },
+                HttpError::Client(_) => Error::Network(message),
+            },
+            ClientError::InvalidToken
+            | ClientError::TokenCallbackFn(_)
+            | ClientError::CacheFile(_)
+            | ClientError::Cli(_) => Error::Auth(message),
+            ClientError::Io(_) => Error::Io(message),
+            ClientError::ParseJson(_) | ClientError::ParseUrl(_) | ClientError::Model(_) => {
+                Error::Network(message)
+            }
+        }
+    }
+
+    // the token endpoint answers bad credentials with 400, anything but the connection is the
+    // authorization's fault there
+    pub fn spotify_auth(context: &str, err: ClientError) -> Error {
+        match Error::spotify(context, err) {
+            err @ (Error::Network(_) | Error::RateLimited(_) | Error::Io(_)) => err,
+            err => Error::Auth(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message)
+            | Error::Auth(message)
+            | Error::InvalidId(message)
+            | Error::NotFound(message)
+            | Error::RateLimited(message)
+            | Error::Network(message)
+            | Error::Search(message)
+            | Error::Download(message)
+            | Error::Tagging(message)
+            | Error::Io(message) => write!(f, "{}", message),
+            Error::PartialFailure { failed, total } => {
+                write!(f, "{} of {} failed", failed, total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_exits_with_its_own_code() {
+        let errors = [
+            Error::PartialFailure {
+                failed: 1,
+                total: 2,
+            },
+            Error::Config(String::new()),
+            Error::Auth(String::new()),
+            Error::InvalidId(String::new()),
+            Error::NotFound(String::new()),
+            Error::RateLimited(String::new()),
+            Error::Network(String::new()),
+            Error::Search(String::new()),
+            Error::Download(String::new()),
+            Error::Tagging(String::new()),
+            Error::Io(String::new()),
+        ];
+
+        let codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+        assert_eq!(codes, (1..=11).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn sorts_spotify_errors_by_their_cause() {
+        let err = Error::spotify("Couldn't fetch the album", ClientError::InvalidToken);
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.to_string().starts_with("Couldn't fetch the album: "));
+
+        let io = std::io::Error::other("disk full");
+        let err = Error::spotify("Couldn't cache the token", ClientError::Io(io));
+        assert!(matches!(err, Error::Io(_)));
+    }
+
+    #[test]
+    fn blames_the_authorization_unless_the_connection_failed() {
+        let json = serde_json::from_str::<u32>("{").unwrap_err();
+        let err = Error::spotify_auth("Couldn't log in", ClientError::ParseJson(json));
+        assert!(matches!(err, Error::Network(_)));
+
+        let err = Error::spotify_auth("Couldn't log in", ClientError::Cli("no".to_string()));
+        assert!(matches!(err, Error::Auth(_)));
+    }
+
+    #[test]
+    fn describes_a_partial_failure() {
+        let err = Error::PartialFailure {
+            failed: 2,
+            total: 10,
+        };
+        assert_eq!(err.to_string(), "2 of 10 failed");
+    }
+}
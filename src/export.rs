@@ -0,0 +1,107 @@
+use crate::{
+    cli::Config,
+    downloader::track_path,
+    error::Error,
+    import::TrackRecord,
+    models::spotify::{SpotifyEntity, SpotifyTrack},
+};
+use clap::ValueEnum;
+use std::{
+    fmt,
+    fs::{create_dir_all, write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    M3u,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Json => write!(f, "json"),
+            ExportFormat::M3u => write!(f, "m3u"),
+        }
+    }
+}
+
+// the export sits next to the directory the tracks are downloaded into, named like it
+pub fn export_path(entity: &SpotifyEntity, format: ExportFormat, cli_args: &Config) -> PathBuf {
+    cli_args.file_path.join(format!(
+        "{}.{}",
+        entity.name().replace(['/', '\\'], "-"),
+        format
+    ))
+}
+
+// writes the entity in the format and returns the file it was written to. csv and json are the
+// formats import reads back, the m3u lists the files download saves the tracks as
+pub fn export_entity(
+    entity: &SpotifyEntity,
+    format: ExportFormat,
+    cli_args: &Config,
+) -> Result<PathBuf, Error> {
+    let path = export_path(entity, format, cli_args);
+    let contents = match format {
+        ExportFormat::Csv => tracks_csv(entity.tracks())?,
+        ExportFormat::Json => entity_json(entity)?,
+        ExportFormat::M3u => playlist_m3u(entity, cli_args),
+    };
+
+    create_dir_all(&cli_args.file_path).map_err(|err| {
+        Error::Io(format!(
+            "{:?} couldn't be created: {}",
+            cli_args.file_path, err
+        ))
+    })?;
+    write(&path, contents)
+        .map_err(|err| Error::Io(format!("{:?} couldn't be written: {}", path, err)))?;
+    Ok(path)
+}
+
+fn tracks_csv(tracks: &[SpotifyTrack]) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for track in tracks {
+        writer
+            .serialize(TrackRecord::from(track.clone()))
+            .map_err(|err| Error::Io(format!("{} couldn't be serialized: {}", track.name, err)))?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| Error::Io(format!("Tracks couldn't be serialized: {}", err)))?;
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+// albums and playlists as a whole, single tracks and artists as the list of their tracks
+fn entity_json(entity: &SpotifyEntity) -> Result<String, Error> {
+    let json = match entity {
+        SpotifyEntity::Album(_) | SpotifyEntity::Playlist(_) => {
+            serde_json::to_string_pretty(entity)
+        }
+        SpotifyEntity::Track(_) | SpotifyEntity::Artist(_) => {
+            serde_json::to_string_pretty(entity.tracks())
+        }
+    };
+    json.map_err(|err| Error::Io(format!("{} couldn't be serialized: {}", entity.name(), err)))
+}
+
+// extended m3u with the paths relative to the playlist file
+fn playlist_m3u(entity: &SpotifyEntity, cli_args: &Config) -> String {
+    let directory = entity.directory(Path::new(""));
+    let mut m3u = String::from("#EXTM3U\n");
+
+    for track in entity.tracks() {
+        m3u.push_str(&format!(
+            "#EXTINF:{},{} - {}\n{}\n",
+            track.duration_ms / 1000,
+            track.artists.join(", "),
+            track.name,
+            track_path(&directory, track, cli_args).display()
+        ));
+    }
+    m3u
+}
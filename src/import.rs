@@ -0,0 +1,303 @@
+use crate::{
+    error::Error,
+    models::spotify::{SpotifyAlbum, SpotifyPlaylist, SpotifyTrack},
+};
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use std::{fs::read_to_string, path::Path};
+
+// joins the artists in a csv field, commas are part of too many artist names
+pub const ARTIST_SEPARATOR: &str = "; ";
+
+// a track as a row of the native csv format, the same fields as SpotifyTrack
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackRecord {
+    pub id: String,
+    pub name: String,
+    pub artists: String,
+    pub album_name: String,
+    #[serde(default)]
+    pub album_cover: String,
+    #[serde(default = "first")]
+    pub disc_number: i32,
+    #[serde(default = "first")]
+    pub track_number: u32,
+    pub duration_ms: u32,
+    #[serde(default)]
+    pub isrc: Option<String>,
+}
+
+fn first<T: From<u8>>() -> T {
+    T::from(1)
+}
+
+impl From<SpotifyTrack> for TrackRecord {
+    fn from(track: SpotifyTrack) -> Self {
+        TrackRecord {
+            id: track.id,
+            name: track.name,
+            artists: track.artists.join(ARTIST_SEPARATOR),
+            album_name: track.album_name,
+            album_cover: track.album_cover,
+            disc_number: track.disc_number,
+            track_number: track.track_number,
+            duration_ms: track.duration_ms,
+            isrc: track.isrc,
+        }
+    }
+}
+
+impl From<TrackRecord> for SpotifyTrack {
+    fn from(record: TrackRecord) -> Self {
+        SpotifyTrack {
+            id: record.id,
+            name: record.name,
+            artists: split_artists(&record.artists, ARTIST_SEPARATOR.trim()),
+            album_name: record.album_name,
+            album_cover: record.album_cover,
+            disc_number: record.disc_number,
+            track_number: record.track_number,
+            duration_ms: record.duration_ms,
+            isrc: record.isrc.filter(|isrc| !isrc.is_empty()),
+        }
+    }
+}
+
+// a json file holds a playlist or an album as info --json and export write them, or only the tracks
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportedJson {
+    Tracks(Vec<SpotifyTrack>),
+    Playlist(SpotifyPlaylist),
+    Album(SpotifyAlbum),
+}
+
+fn split_artists(artists: &str, separator: &str) -> Vec<String> {
+    artists
+        .split(separator)
+        .map(str::trim)
+        .filter(|artist| !artist.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// the tracks of an exported playlist as one playlist named after the file, or after the playlist
+// in json files that carry its name
+pub fn read_import_file(path: &Path) -> Result<SpotifyPlaylist, Error> {
+    let contents = read_to_string(path)
+        .map_err(|err| Error::Io(format!("{:?} couldn't be read: {}", path, err)))?;
+    let file_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported".to_string());
+
+    let (name, tracks) = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => match serde_json::from_str::<ImportedJson>(&contents) {
+            Ok(ImportedJson::Tracks(tracks)) => (file_name, tracks),
+            Ok(ImportedJson::Playlist(playlist)) => (playlist.name, playlist.tracks),
+            Ok(ImportedJson::Album(album)) => (album.name, album.tracks),
+            Err(err) => {
+                return Err(Error::Config(format!(
+                    "{:?} isn't a list of tracks, a playlist or an album: {}",
+                    path, err
+                )))
+            }
+        },
+        Some("csv") => (file_name, read_csv(&contents, path)?),
+        _ => {
+            return Err(Error::Config(format!(
+                "Import file {:?} has to be a .json or .csv file",
+                path
+            )))
+        }
+    };
+
+    Ok(SpotifyPlaylist {
+        name,
+        number_of_songs: tracks.len() as u32,
+        tracks,
+        cover_url: Vec::new(),
+    })
+}
+
+fn read_csv(contents: &str, path: &Path) -> Result<Vec<SpotifyTrack>, Error> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|err| Error::Config(format!("{:?} has no header row: {}", path, err)))?
+        .clone();
+
+    let exportify = headers.iter().any(|header| header == "Track URI");
+    let mut tracks = Vec::new();
+    for (number, row) in reader.records().enumerate() {
+        // the header is the first line
+        let line = number + 2;
+        let row = row.map_err(|err| Error::Config(format!("{:?}:{}: {}", path, line, err)))?;
+        let track = if exportify {
+            exportify_track(&headers, &row)
+        } else {
+            row.deserialize::<TrackRecord>(Some(&headers))
+                .map(SpotifyTrack::from)
+                .map_err(|err| err.to_string())
+        };
+        tracks.push(track.map_err(|err| Error::Config(format!("{:?}:{}: {}", path, line, err)))?);
+    }
+
+    Ok(tracks)
+}
+
+// exportify changed its columns over time, every field is looked up under the names it had
+fn exportify_track(headers: &StringRecord, row: &StringRecord) -> Result<SpotifyTrack, String> {
+    let field = |names: &[&str]| -> Option<&str> {
+        names.iter().find_map(|name| {
+            headers
+                .iter()
+                .position(|header| header == *name)
+                .and_then(|index| row.get(index))
+                .filter(|value| !value.is_empty())
+        })
+    };
+    let number = |names: &[&str], name: &str| -> Result<Option<u32>, String> {
+        field(names)
+            .map(|value| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("{} {} isn't a number", name, value))
+            })
+            .transpose()
+    };
+
+    let name = field(&["Track Name"]).ok_or("Track Name is missing")?;
+    Ok(SpotifyTrack {
+        // local files in a playlist have no spotify id
+        id: field(&["Track URI"])
+            .and_then(|uri| uri.strip_prefix("spotify:track:"))
+            .unwrap_or_default()
+            .to_string(),
+        name: name.to_string(),
+        artists: split_artists(field(&["Artist Name(s)"]).unwrap_or_default(), ","),
+        album_name: field(&["Album Name"]).unwrap_or_default().to_string(),
+        album_cover: field(&["Album Image URL"]).unwrap_or_default().to_string(),
+        disc_number: number(&["Disc Number"], "Disc Number")?.unwrap_or(1) as i32,
+        track_number: number(&["Track Number"], "Track Number")?.unwrap_or(1),
+        duration_ms: number(&["Track Duration (ms)", "Duration (ms)"], "Duration")?
+            .ok_or("Duration is missing")?,
+        isrc: field(&["ISRC"]).map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(contents: &str) -> Result<Vec<SpotifyTrack>, Error> {
+        read_csv(contents, Path::new("playlist.csv"))
+    }
+
+    #[test]
+    fn reads_exportify_columns() {
+        let tracks = read(
+            "Track URI,Track Name,Artist Name(s),Album Name,Disc Number,Track Number,Track Duration (ms),ISRC\n\
+             spotify:track:4uLU6hMCjMI75M1A2tKUQC,Never Gonna Give You Up,Rick Astley,Whenever You Need Somebody,1,1,213573,GBARL9300135\n",
+        )
+        .unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        let track = &tracks[0];
+        assert_eq!(track.id, "4uLU6hMCjMI75M1A2tKUQC");
+        assert_eq!(track.name, "Never Gonna Give You Up");
+        assert_eq!(track.artists, vec!["Rick Astley"]);
+        assert_eq!(track.duration_ms, 213573);
+        assert_eq!(track.isrc.as_deref(), Some("GBARL9300135"));
+    }
+
+    #[test]
+    fn reads_older_exportify_duration_column() {
+        let tracks = read(
+            "Track URI,Track Name,Artist Name(s),Album Name,Duration (ms)\n\
+             spotify:track:1,Under Pressure,\"Queen,David Bowie\",Hot Space,248440\n",
+        )
+        .unwrap();
+
+        let track = &tracks[0];
+        assert_eq!(track.artists, vec!["Queen", "David Bowie"]);
+        assert_eq!(track.duration_ms, 248440);
+        assert_eq!(track.disc_number, 1);
+        assert_eq!(track.track_number, 1);
+        assert_eq!(track.isrc, None);
+    }
+
+    #[test]
+    fn keeps_local_files_without_an_id() {
+        let tracks = read(
+            "Track URI,Track Name,Track Duration (ms)\n\
+             spotify:local:::Demo:180,Demo,180000\n",
+        )
+        .unwrap();
+
+        assert_eq!(tracks[0].id, "");
+        assert_eq!(tracks[0].name, "Demo");
+    }
+
+    #[test]
+    fn rejects_a_missing_duration() {
+        let err = read(
+            "Track URI,Track Name,Track Duration (ms)\n\
+             spotify:track:1,Demo,\n",
+        )
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("playlist.csv\":2: Duration is missing"));
+    }
+
+    #[test]
+    fn rejects_a_duration_that_is_not_a_number() {
+        let err = read(
+            "Track URI,Track Name,Track Duration (ms)\n\
+             spotify:track:1,Demo,3:00\n",
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("Duration 3:00 isn't a number"));
+    }
+
+    #[test]
+    fn reads_the_native_format() {
+        let tracks = read(
+            "id,name,artists,album_name,duration_ms,isrc\n\
+             1,Under Pressure,Queen; David Bowie,Hot Space,248440,\n",
+        )
+        .unwrap();
+
+        let track = &tracks[0];
+        assert_eq!(track.id, "1");
+        assert_eq!(track.artists, vec!["Queen", "David Bowie"]);
+        assert_eq!(track.disc_number, 1);
+        assert_eq!(track.isrc, None);
+    }
+
+    #[test]
+    fn writes_and_reads_back_the_native_format() {
+        let track = SpotifyTrack {
+            id: "1".to_string(),
+            name: "Under Pressure".to_string(),
+            artists: vec!["Queen".to_string(), "David Bowie".to_string()],
+            album_name: "Hot Space".to_string(),
+            album_cover: String::new(),
+            disc_number: 1,
+            track_number: 11,
+            duration_ms: 248440,
+            isrc: Some("GBUM71029604".to_string()),
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(TrackRecord::from(track.clone())).unwrap();
+        let contents = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        let tracks = read(&contents).unwrap();
+        assert_eq!(tracks[0].artists, track.artists);
+        assert_eq!(tracks[0].track_number, 11);
+        assert_eq!(tracks[0].isrc, track.isrc);
+    }
+}
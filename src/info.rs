@@ -0,0 +1,115 @@
+use crate::{
+    error::Error,
+    models::spotify::{Spotify, SpotifyEntity, SpotifyLink},
+    search::fit,
+};
+use colored::Colorize;
+use serde::Serialize;
+
+const TITLE_WIDTH: usize = 40;
+const ARTISTS_WIDTH: usize = 30;
+
+// everything info knows about a link, the entity's own fields are inlined
+#[derive(Debug, Serialize)]
+pub struct EntityInfo<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub link: String,
+    pub total_duration_ms: u64,
+    #[serde(flatten)]
+    pub entity: &'a SpotifyEntity,
+}
+
+impl<'a> EntityInfo<'a> {
+    pub fn new(link: &SpotifyLink, entity: &'a SpotifyEntity) -> Self {
+        EntityInfo {
+            kind: match link.kind {
+                Spotify::Track => "track",
+                Spotify::Album => "album",
+                Spotify::Playlist | Spotify::Library => "playlist",
+                Spotify::Artist => "artist",
+            },
+            link: link.to_string(),
+            total_duration_ms: entity
+                .tracks()
+                .iter()
+                .map(|track| u64::from(track.duration_ms))
+                .sum(),
+            entity,
+        }
+    }
+}
+
+// m:ss, or h:mm:ss for whole albums and playlists
+pub fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+pub fn print_info(info: &EntityInfo) {
+    let tracks = info.entity.tracks();
+
+    println!("{}", info.entity.name().bright_yellow().bold());
+    // podcast episodes and unavailable tracks are counted by spotify but can't be listed
+    let count = if info.entity.is_complete() {
+        tracks.len().to_string()
+    } else {
+        format!("{} of {}", tracks.len(), info.entity.number_of_songs())
+    };
+    println!(
+        "{} · {} tracks · {}",
+        info.kind,
+        count,
+        format_duration(info.total_duration_ms)
+    );
+    println!("{}", info.link.cyan());
+    if let SpotifyEntity::Artist(artist) = info.entity {
+        println!("Followers: {}", artist.followers);
+        if !artist.genres.is_empty() {
+            println!("Genres: {}", artist.genres.join(", "));
+        }
+    }
+    if let Some(cover_url) = info.entity.cover_url() {
+        println!("Cover: {}", cover_url);
+    }
+    println!();
+
+    println!(
+        "{}",
+        format!(
+            "{:>3}  {}  {}  {:>8}  {}",
+            "#",
+            fit("Title", TITLE_WIDTH),
+            fit("Artists", ARTISTS_WIDTH),
+            "Duration",
+            "ISRC"
+        )
+        .bright_yellow()
+    );
+    for (number, track) in tracks.iter().enumerate() {
+        println!(
+            "{:>3}  {}  {}  {:>8}  {}",
+            number + 1,
+            fit(&track.name, TITLE_WIDTH).bright_blue(),
+            fit(&track.artists.join(", "), ARTISTS_WIDTH),
+            format_duration(u64::from(track.duration_ms)),
+            track.isrc.as_deref().unwrap_or("-").dimmed()
+        );
+    }
+}
+
+pub fn print_info_json(info: &EntityInfo) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(info)
+        .map_err(|err| Error::Io(format!("Info couldn't be serialized: {}", err)))?;
+    println!("{}", json);
+    Ok(())
+}
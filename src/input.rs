@@ -0,0 +1,88 @@
+use crate::error::Error;
+use crate::models::spotify::SpotifyLink;
+use std::{
+    fs::File,
+    io::{stdin, BufRead, BufReader},
+    path::Path,
+};
+
+// stands for stdin, both as a link and as the input file
+pub const STDIN: &str = "-";
+
+// one link per line, blank lines and everything after a # are ignored
+pub fn read_links(reader: impl BufRead, source: &str) -> Result<Vec<SpotifyLink>, Error> {
+    let mut links = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line =
+            line.map_err(|err| Error::Io(format!("{} couldn't be read: {}", source, err)))?;
+        let link = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => &line,
+        }
+        .trim();
+
+        if link.is_empty() {
+            continue;
+        }
+        links.push(
+            link.parse::<SpotifyLink>()
+                .map_err(|err| Error::InvalidId(format!("{}:{}: {}", source, number + 1, err)))?,
+        );
+    }
+
+    Ok(links)
+}
+
+pub fn read_input_file(path: &Path) -> Result<Vec<SpotifyLink>, Error> {
+    if path == Path::new(STDIN) {
+        return read_stdin();
+    }
+
+    let file = File::open(path)
+        .map_err(|err| Error::Io(format!("Input file {:?} couldn't be opened: {}", path, err)))?;
+    read_links(BufReader::new(file), &path.display().to_string())
+}
+
+pub fn read_stdin() -> Result<Vec<SpotifyLink>, Error> {
+    read_links(stdin().lock(), "stdin")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let input = "# favourites\n\
+                     https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC # rick\n\
+                     \n\
+                     \x20  spotify:album:1DFixLWuPkv3KT3TnV35m3\n\
+                     #https://open.spotify.com/track/skipped\n";
+
+        let links: Vec<String> = read_links(input.as_bytes(), "links.txt")
+            .unwrap()
+            .iter()
+            .map(SpotifyLink::to_string)
+            .collect();
+
+        assert_eq!(
+            links,
+            vec![
+                "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+                "https://open.spotify.com/album/1DFixLWuPkv3KT3TnV35m3",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_an_invalid_link() {
+        let input = "# header\nspotify:track:4uLU6hMCjMI75M1A2tKUQC\nnot a link\n";
+
+        let err = read_links(input.as_bytes(), "links.txt")
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("links.txt:3"));
+    }
+}
@@ -0,0 +1,39 @@
+// yuck_premium as a library, for services that download spotify links themselves.
+// the Downloader resolves links, plans and downloads them and sends every event of a run
+// to a channel; the yuck_premium binary is a command line on top of the same modules
+pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod cli;
+pub mod commands;
+pub mod downloader;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod info;
+pub mod input;
+pub mod local;
+pub mod logging;
+pub mod matcher;
+pub mod metadata;
+pub mod models;
+pub mod output;
+pub mod overrides;
+pub mod paths;
+pub mod plan;
+pub mod provider;
+pub mod report;
+pub mod search;
+pub mod settings;
+pub mod source;
+pub mod spotify;
+pub mod transcode;
+pub mod verify;
+
+pub use api::{Downloader, DownloaderBuilder};
+pub use cli::AudioFormat;
+pub use error::Error;
+pub use models::cli::{Bitrate, Codec, Source};
+pub use models::spotify::{SpotifyEntity, SpotifyLink, SpotifyTrack};
+pub use output::Event;
+pub use report::{ReportEntry, TrackStatus};
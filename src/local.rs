@@ -0,0 +1,306 @@
+use crate::{
+    error::Error,
+    matcher::Candidate,
+    models::spotify::SpotifyTrack,
+    source::{AudioSource, SourceAudio},
+    verify::DURATION_TOLERANCE,
+};
+use futures::future::BoxFuture;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::ItemKey;
+use log::{debug, info, warn};
+use reqwest::Url;
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::OnceCell, task::spawn_blocking};
+
+// extensions of the files that are read while indexing, everything else is passed over
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "mpa", "flac", "opus", "ogg", "m4a", "aac", "wav", "aif", "aiff", "wv", "ape",
+];
+
+// a file of the library with the tags it is matched by
+#[derive(Clone, Debug)]
+pub struct LocalFile {
+    pub path: PathBuf,
+    pub isrc: Option<String>,
+    pub title: String,
+    pub artist: String,
+    pub duration: Duration,
+}
+
+impl LocalFile {
+    fn read(path: PathBuf) -> Option<LocalFile> {
+        let tagged_file = match Probe::open(&path)
+            .and_then(|probe| Ok(probe.guess_file_type()?))
+            .and_then(|probe| probe.read())
+        {
+            Ok(tagged_file) => tagged_file,
+            Err(err) => {
+                debug!("{:?} isn't indexed: {}", path, err);
+                return None;
+            }
+        };
+        let tag = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())?;
+
+        Some(LocalFile {
+            isrc: tag
+                .get_string(&ItemKey::Isrc)
+                .map(|isrc| isrc.trim().to_uppercase())
+                .filter(|isrc| !isrc.is_empty()),
+            title: tag.title().as_deref().unwrap_or_default().to_string(),
+            artist: tag.artist().as_deref().unwrap_or_default().to_string(),
+            duration: tagged_file.properties().duration(),
+            path,
+        })
+    }
+
+    // same isrc, or else the same title by one of the artists at about the same length
+    fn matches(&self, spotify_song: &SpotifyTrack) -> bool {
+        if let (Some(isrc), Some(spotify_isrc)) = (&self.isrc, &spotify_song.isrc) {
+            return isrc.eq_ignore_ascii_case(spotify_isrc);
+        }
+
+        let expected = Duration::from_millis(u64::from(spotify_song.duration_ms));
+        let artist = normalize(&self.artist);
+        normalize(&self.title) == normalize(&spotify_song.name)
+            && spotify_song
+                .artists
+                .iter()
+                .any(|name| artist.contains(&normalize(name)))
+            && self.duration.abs_diff(expected) <= DURATION_TOLERANCE
+    }
+
+    fn to_candidate(&self) -> Option<Candidate> {
+        let url = Url::from_file_path(&self.path).ok()?;
+        Some(Candidate {
+            id: self.path.display().to_string(),
+            url: url.to_string(),
+            title: format!("{} - {}", self.title, self.artist),
+            duration: Some(self.duration.as_secs_f64()),
+            score: 0.0,
+            pinned: false,
+        })
+    }
+}
+
+// lowercase words without punctuation, so "Don't Stop" and "dont stop" are the same title
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// every tagged audio file below the directory
+pub fn index_library(directory: &Path) -> Vec<LocalFile> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = match read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("{:?} couldn't be indexed: {}", directory, err);
+                continue;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            // the type of the entry itself, a link to a directory above would be indexed forever
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() && path.is_dir() => {
+                    debug!("{:?} is a link to a directory, it isn't indexed", path);
+                }
+                Ok(file_type) if file_type.is_dir() => directories.push(path),
+                Ok(_) if is_audio(&path) => files.extend(LocalFile::read(path)),
+                Ok(_) => {}
+                Err(err) => warn!("{:?} couldn't be indexed: {}", path, err),
+            }
+        }
+    }
+
+    files
+}
+
+// files the user already owns, tracks found there are copied or transcoded instead of downloaded,
+// the copy is retagged and the library keeps its own tags. everything else goes to the fallback
+// source
+#[derive(Debug)]
+pub struct LocalLibrary {
+    directory: PathBuf,
+    // indexed on the first search, runs that match nothing never read the library
+    index: OnceCell<Vec<LocalFile>>,
+    fallback: Arc<dyn AudioSource>,
+}
+
+impl LocalLibrary {
+    // the directory is made absolute, files are handed to the source as file:// urls
+    pub fn new(directory: PathBuf, fallback: Arc<dyn AudioSource>) -> Result<Self, Error> {
+        let directory = directory.canonicalize().map_err(|err| {
+            Error::Config(format!("Library {:?} can't be read: {}", directory, err))
+        })?;
+        if !directory.is_dir() {
+            return Err(Error::Config(format!(
+                "Library {:?} isn't a directory",
+                directory
+            )));
+        }
+
+        Ok(LocalLibrary {
+            directory,
+            index: OnceCell::new(),
+            fallback,
+        })
+    }
+
+    async fn index(&self) -> &[LocalFile] {
+        self.index
+            .get_or_init(|| async {
+                let directory = self.directory.clone();
+                let files = spawn_blocking(move || index_library(&directory))
+                    .await
+                    .unwrap_or_else(|err| {
+                        warn!("Indexing the library failed: {}", err);
+                        Vec::new()
+                    });
+                info!("Indexed {} files in {:?}", files.len(), self.directory);
+                files
+            })
+            .await
+    }
+}
+
+impl AudioSource for LocalLibrary {
+    fn name(&self) -> &'static str {
+        "library"
+    }
+
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Result<Vec<Candidate>, Error>> {
+        Box::pin(async move {
+            let candidates = self.local_candidates(spotify_song).await;
+            if candidates.is_empty() {
+                debug!(
+                    "{} isn't in the library, searching {}",
+                    spotify_song.name,
+                    self.fallback.name()
+                );
+                return self.fallback.search(query, spotify_song).await;
+            }
+            Ok(candidates)
+        })
+    }
+
+    // a file of the library is transcoded from where it is, everything else is downloaded
+    fn download<'a>(
+        &'a self,
+        candidate: &'a Candidate,
+        raw: &'a Path,
+    ) -> BoxFuture<'a, Result<SourceAudio, Error>> {
+        let path = Url::parse(&candidate.url)
+            .ok()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok());
+        match path {
+            Some(path) => {
+                info!("Taking {:?} from the library", path);
+                Box::pin(async move {
+                    Ok(SourceAudio {
+                        path,
+                        temporary: false,
+                    })
+                })
+            }
+            None => self.fallback.download(candidate, raw),
+        }
+    }
+
+    fn local_candidates<'a>(
+        &'a self,
+        spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Vec<Candidate>> {
+        Box::pin(async move {
+            self.index()
+                .await
+                .iter()
+                .filter(|file| file.matches(spotify_song))
+                .filter_map(LocalFile::to_candidate)
+                .collect()
+        })
+    }
+
+    fn fallback(&self) -> Option<&dyn AudioSource> {
+        Some(self.fallback.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(isrc: Option<&str>) -> SpotifyTrack {
+        SpotifyTrack {
+            id: "7o2CTH4ctstm8TNelqjb51".to_string(),
+            name: "Don't Stop Me Now".to_string(),
+            artists: vec!["Queen".to_string()],
+            album_name: "Jazz".to_string(),
+            album_cover: String::new(),
+            disc_number: 1,
+            track_number: 12,
+            duration_ms: 209413,
+            isrc: isrc.map(str::to_string),
+        }
+    }
+
+    fn file(isrc: Option<&str>, title: &str, artist: &str, seconds: u64) -> LocalFile {
+        LocalFile {
+            path: PathBuf::from("/music/queen/dont-stop-me-now.flac"),
+            isrc: isrc.map(str::to_string),
+            title: title.to_string(),
+            artist: artist.to_string(),
+            duration: Duration::from_secs(seconds),
+        }
+    }
+
+    #[test]
+    fn matches_by_isrc_whatever_the_tags_say() {
+        let song = song(Some("GBUM71029601"));
+        assert!(file(Some("gbum71029601"), "Live Version", "Someone", 300).matches(&song));
+        assert!(!file(Some("GBUM71029602"), "Don't Stop Me Now", "Queen", 209).matches(&song));
+    }
+
+    #[test]
+    fn matches_by_title_artist_and_length_without_an_isrc() {
+        let song = song(Some("GBUM71029601"));
+        assert!(file(None, "dont stop me now", "Queen", 209).matches(&song));
+        assert!(file(None, "Don't Stop Me Now", "Queen & Freddie Mercury", 212).matches(&song));
+    }
+
+    #[test]
+    fn rejects_another_title_artist_or_length() {
+        let song = song(None);
+        assert!(!file(None, "Don't Stop Me Now (Live)", "Queen", 209).matches(&song));
+        assert!(!file(None, "Don't Stop Me Now", "McFly", 209).matches(&song));
+        assert!(!file(None, "Don't Stop Me Now", "Queen", 260).matches(&song));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::paths::log_dir;
+use flexi_logger::{
+    colored_default_format, detailed_format, Cleanup, Criterion, Duplicate, FileSpec, Logger,
+    LoggerHandle, Naming,
+};
+use log::LevelFilter;
+
+const LOG_FILE_NAME: &str = "yuck_premium";
+// a run that logs more than this continues in a new file
+const LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+// log files of older runs are deleted
+const KEPT_LOG_FILES: usize = 10;
+
+// -q only shows errors, every -v shows one level more
+pub fn level_filter(verbosity: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+// logs go to stderr so they never mix with the json stream on stdout.
+// RUST_LOG takes precedence over the flags, e.g. to see the logs of the libraries too.
+// the handle has to live until the end of main, the log file is flushed when it's dropped
+pub fn init_logger(level: LevelFilter, log_file: bool) -> Option<LoggerHandle> {
+    // the log file keeps at least the info messages, whatever is shown on the terminal
+    let logged = if log_file {
+        level.max(LevelFilter::Info)
+    } else {
+        level
+    };
+    let spec = format!("{}={}", env!("CARGO_CRATE_NAME"), logged);
+    let logger = match Logger::try_with_env_or_str(spec) {
+        Ok(logger) => logger,
+        Err(err) => {
+            eprintln!("Logger couldn't be configured: {}", err);
+            return None;
+        }
+    }
+    .format_for_stderr(colored_default_format);
+
+    let logger = if log_file {
+        // every run starts a new file, the one of the previous run is renamed after its start
+        logger
+            .log_to_file(
+                FileSpec::default()
+                    .directory(log_dir())
+                    .basename(LOG_FILE_NAME)
+                    .suppress_timestamp(),
+            )
+            .format_for_files(detailed_format)
+            .rotate(
+                Criterion::Size(LOG_FILE_SIZE),
+                Naming::Timestamps,
+                Cleanup::KeepLogFiles(KEPT_LOG_FILES),
+            )
+            .duplicate_to_stderr(Duplicate::from(level))
+    } else {
+        logger
+    };
+
+    match logger.start() {
+        Ok(handle) => Some(handle),
+        Err(err) => {
+            eprintln!("Logger couldn't be started: {}", err);
+            None
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{models::spotify::SpotifyTrack, source::AudioSource};
+use log::{debug, error};
+
+// number of search results considered for every track
+pub const SEARCH_CANDIDATES: usize = 5;
+
+// a search result that could be the audio of a spotify track
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub duration: Option<f64>,
+    pub score: f64,
+    // chosen by hand, so its length isn't held against the spotify track
+    pub pinned: bool,
+}
+
+impl Candidate {
+    pub fn pinned(url: String) -> Candidate {
+        Candidate {
+            id: url.clone(),
+            title: url.clone(),
+            url,
+            duration: None,
+            score: 1.0,
+            pinned: true,
+        }
+    }
+}
+
+// searches the source for the query and returns the results ordered from the best match to the worst
+pub async fn search_candidates(
+    source: &dyn AudioSource,
+    query: String,
+    spotify_song: &SpotifyTrack,
+) -> Vec<Candidate> {
+    let mut candidates = match source.search(&query, spotify_song).await {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            error!(
+                "Error while searching {} for {} - {}: {}",
+                source.name(),
+                spotify_song.name,
+                spotify_song.artists.join(","),
+                err
+            );
+            return Vec::new();
+        }
+    };
+
+    for candidate in &mut candidates {
+        candidate.score = score_candidate(spotify_song, &candidate.title, candidate.duration);
+    }
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    for candidate in &candidates {
+        debug!(
+            "Candidate {} ({:?}s) for {}: {:.2}",
+            candidate.title, candidate.duration, spotify_song.name, candidate.score
+        );
+    }
+    candidates
+}
+
+// scores a result between 0 and 1, mostly on how close its length is to the spotify track
+// and partly on how many words of the track name and artists appear in its title
+pub fn score_candidate(spotify_song: &SpotifyTrack, title: &str, duration: Option<f64>) -> f64 {
+    let expected = f64::from(spotify_song.duration_ms) / 1000.0;
+    let duration_score = match duration {
+        Some(duration) => (1.0 - (duration - expected).abs() / 30.0).max(0.0),
+        None => 0.5,
+    };
+
+    let title = title.to_lowercase();
+    let words: Vec<String> = spotify_song
+        .name
+        .split_whitespace()
+        .chain(
+            spotify_song
+                .artists
+                .iter()
+                .flat_map(|a| a.split_whitespace()),
+        )
+        .map(|word| word.to_lowercase())
+        .collect();
+    let title_score = if words.is_empty() {
+        0.0
+    } else {
+        let found = words
+            .iter()
+            .filter(|word| title.contains(word.as_str()))
+            .count();
+        found as f64 / words.len() as f64
+    };
+
+    0.6 * duration_score + 0.4 * title_score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song() -> SpotifyTrack {
+        SpotifyTrack {
+            id: "4uLU6hMCjMI75M1A2tKUQC".to_string(),
+            name: "Never Gonna Give You Up".to_string(),
+            artists: vec!["Rick Astley".to_string()],
+            album_name: "Whenever You Need Somebody".to_string(),
+            album_cover: String::new(),
+            disc_number: 1,
+            track_number: 1,
+            duration_ms: 213000,
+            isrc: None,
+        }
+    }
+
+    fn assert_close(score: f64, expected: f64) {
+        assert!((score - expected).abs() < 1e-9, "{} != {}", score, expected);
+    }
+
+    #[test]
+    fn scores_a_title_and_length_match_as_one() {
+        let title = "Rick Astley - Never Gonna Give You Up (Official Video)";
+        assert_close(score_candidate(&song(), title, Some(213.0)), 1.0);
+    }
+
+    #[test]
+    fn scores_the_length_by_its_distance_to_the_spotify_track() {
+        let title = "RICK ASTLEY never gonna give you up";
+        assert_close(
+            score_candidate(&song(), title, Some(228.0)),
+            0.6 * 0.5 + 0.4,
+        );
+        assert_close(score_candidate(&song(), title, Some(600.0)), 0.4);
+        assert_close(score_candidate(&song(), title, None), 0.6 * 0.5 + 0.4);
+    }
+
+    #[test]
+    fn scores_the_title_by_the_words_it_contains() {
+        // 2 of the 7 words of the name and artist
+        assert_close(
+            score_candidate(&song(), "Rick Astley live", Some(213.0)),
+            0.6 + 0.4 * 2.0 / 7.0,
+        );
+        assert_close(score_candidate(&song(), "Something else", Some(213.0)), 0.6);
+    }
+
+    #[test]
+    fn prefers_the_closer_length() {
+        let song = song();
+        let video = score_candidate(&song, "Never Gonna Give You Up", Some(260.0));
+        let audio = score_candidate(&song, "Never Gonna Give You Up", Some(214.0));
+        assert!(audio > video);
+    }
+}
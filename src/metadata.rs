@@ -1,37 +1,32 @@
-use colored::Colorize;
 use lofty::config::WriteOptions;
 use lofty::picture::{Picture, PictureType};
 use lofty::prelude::*;
 use lofty::probe::Probe;
 use lofty::tag::Tag;
-use log::{error, info};
+use log::{debug, info, warn};
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::error::Error;
 use crate::models::spotify::SpotifyTrack;
-
-pub fn add_metadata(spotify_song: SpotifyTrack, album_art_dir: PathBuf, file_path: PathBuf) {
-    // reads the file type from the path and open the File as File::open
-    let probed_file = match Probe::open(&file_path) {
-        Err(err) => {
-            error!(
-                "Error occured while reading the file_path {:?}: {}",
-                file_path, err
-            );
-            return;
-        }
-
-        Ok(probe) => probe,
-    };
-
-    let mut tagged_file = match probed_file.read() {
-        Ok(file) => file,
-        Err(err) => {
-            error!("Error occured while reading the probe: {}", err);
-            return;
-        }
-    };
+use crate::transcode::resize_cover;
+
+// cover_size is the longest side the cover is scaled down to, 0 leaves the cover out
+pub fn add_metadata(
+    spotify_song: SpotifyTrack,
+    album_art_dir: PathBuf,
+    file_path: PathBuf,
+    cover_size: Option<u32>,
+) -> Result<(), Error> {
+    // the file type is read from the content, lofty doesn't know extensions like mpa
+    let probed_file = Probe::open(&file_path)
+        .and_then(|probe| Ok(probe.guess_file_type()?))
+        .map_err(|err| Error::Tagging(format!("{:?} couldn't be opened: {}", file_path, err)))?;
+
+    let mut tagged_file = probed_file
+        .read()
+        .map_err(|err| Error::Tagging(format!("{:?} couldn't be read: {}", file_path, err)))?;
 
     // primary_tag_mut returns the primary tag type of the file
     let tag = match tagged_file.primary_tag_mut() {
@@ -43,17 +38,15 @@ pub fn add_metadata(spotify_song: SpotifyTrack, album_art_dir: PathBuf, file_pat
             } else {
                 let tag_type = tagged_file.primary_tag_type();
 
-                println!(
-                    "{}",
-                    format!(
-                        "WARN: No tags found, creating a new tag of type: {:?}",
-                        tag_type
-                    )
-                    .yellow()
-                );
+                warn!("No tags found, creating a new tag of type: {:?}", tag_type);
                 tagged_file.insert_tag(Tag::new(tag_type));
 
-                tagged_file.primary_tag_mut().unwrap()
+                tagged_file.primary_tag_mut().ok_or_else(|| {
+                    Error::Tagging(format!(
+                        "{:?} doesn't support {:?} tags",
+                        file_path, tag_type
+                    ))
+                })?
             }
         }
     };
@@ -65,97 +58,80 @@ pub fn add_metadata(spotify_song: SpotifyTrack, album_art_dir: PathBuf, file_pat
     tag.set_disk(spotify_song.disc_number as u32);
     tag.set_track(spotify_song.track_number);
 
-    let image_file = match File::open(album_art_dir.clone()) {
-        Ok(file) => file,
-        Err(err) => {
-            error!("Error while opening the file {:?}: {}", album_art_dir, err);
-            return;
-        }
-    };
-
-    let mut image = BufReader::new(image_file);
+    // retagging replaces the cover instead of adding another one
+    tag.remove_picture_type(PictureType::CoverFront);
+    // a track whose cover couldn't be downloaded is still tagged, just without it
+    match cover_size {
+        Some(0) => debug!("Tagging {:?} without a cover", file_path),
+        _ => match read_cover(&album_art_dir, cover_size) {
+            Ok(mut picture) => {
+                picture.set_pic_type(PictureType::CoverFront);
+                tag.push_picture(picture);
+            }
+            Err(err) => warn!("{}, tagging {:?} without a cover", err, file_path),
+        },
+    }
 
-    let mut picture = match Picture::from_reader(&mut image) {
-        Ok(pic) => pic,
-        Err(err) => {
-            error!("Error occured while reading picture from BufRead: {}", err);
-            return;
-        }
-    };
-    picture.set_pic_type(PictureType::CoverFront);
-    tag.push_picture(picture);
-
-    match tag.save_to_path(file_path.clone(), WriteOptions::default()) {
-        Ok(_) => {
-            info!(
-                "{}",
-                format!("Tag saved to the path: {:?}", file_path).green()
-            );
-        }
-        Err(err) => {
-            error!(
-                "Error occured while saving tag to the path {:?}: {}",
+    tag.save_to_path(&file_path, WriteOptions::default())
+        .map_err(|err| {
+            Error::Tagging(format!(
+                "Tags couldn't be saved to {:?}: {}",
                 file_path, err
-            );
-            return;
-        }
-    }
+            ))
+        })?;
+    info!("Tag saved to the path: {:?}", file_path);
+    Ok(())
 }
 
-pub fn check_metadata(file_path: &PathBuf) {
-    let probed_file = match Probe::open(file_path) {
-        Ok(probe) => probe,
-        Err(err) => {
-            error!(
-                "{}",
-                format!(
-                    "Error occured while reading the file_path {:?}: {}",
-                    file_path, err
-                )
-                .red()
-            );
-            return;
+fn read_cover(album_art_dir: &Path, cover_size: Option<u32>) -> Result<Picture, Error> {
+    let picture = match cover_size {
+        Some(size) => {
+            let image = resize_cover(album_art_dir, size)?;
+            Picture::from_reader(&mut image.as_slice())
         }
-    };
-
-    let tagged_file = match probed_file.read() {
-        Ok(file) => file,
-        Err(err) => {
-            error!(
-                "{}",
-                format!("Error occured while reading the probe: {}", err).red()
-            );
-            return;
+        None => {
+            let image_file = File::open(album_art_dir).map_err(|err| {
+                Error::Io(format!(
+                    "Cover {:?} couldn't be opened: {}",
+                    album_art_dir, err
+                ))
+            })?;
+            Picture::from_reader(&mut BufReader::new(image_file))
         }
     };
 
-    let tag = match tagged_file.primary_tag() {
-        Some(tag) => tag,
-        None => tagged_file.first_tag().expect("No tag found!"),
-    };
+    picture.map_err(|err| {
+        Error::Tagging(format!(
+            "Cover {:?} couldn't be read: {}",
+            album_art_dir, err
+        ))
+    })
+}
 
-    println!(
-        "{}",
-        "------------Audio Information----------".bright_yellow()
-    );
-    println!(
-        "{}",
-        format!("Title: {}", tag.title().as_deref().unwrap_or("None")).bright_blue()
-    );
-    println!(
-        "{}",
-        format!("Artist: {}", tag.artist().as_deref().unwrap_or("None")).bright_blue()
-    );
-    println!(
-        "{}",
-        format!("Album: {}", tag.album().as_deref().unwrap_or("None")).bright_blue()
-    );
-    println!(
-        "{}",
-        format!("Disk Number: {}", tag.disk().unwrap_or(0)).bright_blue()
-    );
-    println!(
-        "{}\n",
-        format!("Track Number: {}", tag.track().unwrap_or(0)).bright_blue()
+// reads the tags back from the file, fails if the file can't be parsed or has no tag
+pub fn check_metadata(file_path: &Path) -> Result<(), Error> {
+    let probed_file = Probe::open(file_path)
+        .and_then(|probe| Ok(probe.guess_file_type()?))
+        .map_err(|err| Error::Tagging(format!("{:?} couldn't be opened: {}", file_path, err)))?;
+
+    let tagged_file = probed_file
+        .read()
+        .map_err(|err| Error::Tagging(format!("{:?} couldn't be read: {}", file_path, err)))?;
+
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+        .ok_or_else(|| Error::Tagging(format!("No tag found in {:?}", file_path)))?;
+
+    debug!(
+        "Tags of {:?}: title {}, artist {}, album {}, disk {}, track {}",
+        file_path,
+        tag.title().as_deref().unwrap_or("None"),
+        tag.artist().as_deref().unwrap_or("None"),
+        tag.album().as_deref().unwrap_or("None"),
+        tag.disk().unwrap_or(0),
+        tag.track().unwrap_or(0)
     );
+
+    Ok(())
 }
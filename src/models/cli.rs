@@ -1,41 +1,34 @@
-use std::{
-    fmt::{self, Display},
-    str::FromStr,
-};
+use std::fmt::{self, Display};
 
-use clap::Subcommand;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
 // The number of bits proccessed over a certain period of time
-#[derive(Debug, Clone, Copy, Subcommand)]
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Bitrate {
     Worst,
+    #[value(alias = "32")]
+    #[serde(alias = "32")]
     Worse = 32,
+    #[value(alias = "96")]
+    #[serde(alias = "96")]
     Poor = 96,
+    #[value(alias = "128")]
+    #[serde(alias = "128")]
     Low = 128,
+    #[value(alias = "192")]
+    #[serde(alias = "192")]
     Medium = 192,
+    #[value(alias = "256")]
+    #[serde(alias = "256")]
     Good = 256,
+    #[value(alias = "320")]
+    #[serde(alias = "320")]
     High = 320,
     Best,
 }
 
-impl FromStr for Bitrate {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "worst" => Ok(Bitrate::Worst),
-            "32" => Ok(Bitrate::Worse),
-            "96" => Ok(Bitrate::Poor),
-            "128" => Ok(Bitrate::Low),
-            "192" => Ok(Bitrate::Medium),
-            "256" => Ok(Bitrate::Good),
-            "320" => Ok(Bitrate::High),
-            "best" => Ok(Bitrate::Best),
-            _ => Err(()),
-        }
-    }
-}
-
 impl Display for Bitrate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -54,26 +47,42 @@ impl Display for Bitrate {
 // Codec determines the compression rate and file siz.
 // Lossy Codec formats compress the file and reduce size
 // But formats that don't compress have high audio quality
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Codec {
     MP3,
     Flac,
-    MPA,
+    Mpa,
     Opus,
+    #[value(alias = "m4a")]
+    #[serde(alias = "m4a")]
+    Aac,
+    Alac,
+    #[value(alias = "ogg")]
+    #[serde(alias = "ogg")]
+    Vorbis,
+    Wav,
+    Aiff,
 }
 
-impl FromStr for Codec {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "mp3" => Ok(Codec::MP3),
-            "flac" => Ok(Codec::Flac),
-            "mpa" => Ok(Codec::MPA),
-            "opus" => Ok(Codec::Opus),
-            _ => Err(()),
+impl Codec {
+    // aac and alac share the mp4 container
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Codec::MP3 => "mp3",
+            Codec::Flac => "flac",
+            Codec::Mpa => "mpa",
+            Codec::Opus => "opus",
+            Codec::Aac | Codec::Alac => "m4a",
+            Codec::Vorbis => "ogg",
+            Codec::Wav => "wav",
+            Codec::Aiff => "aiff",
         }
     }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, Codec::Flac | Codec::Alac | Codec::Wav | Codec::Aiff)
+    }
 }
 
 impl fmt::Display for Codec {
@@ -81,8 +90,33 @@ impl fmt::Display for Codec {
         match self {
             Codec::MP3 => write!(f, "mp3"),
             Codec::Flac => write!(f, "flac"),
-            Codec::MPA => write!(f, "mpa"),
+            Codec::Mpa => write!(f, "mpa"),
             Codec::Opus => write!(f, "opus"),
+            Codec::Aac => write!(f, "aac"),
+            Codec::Alac => write!(f, "alac"),
+            Codec::Vorbis => write!(f, "vorbis"),
+            Codec::Wav => write!(f, "wav"),
+            Codec::Aiff => write!(f, "aiff"),
+        }
+    }
+}
+
+// where the audio of every track is searched for and downloaded from
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Youtube,
+    #[serde(rename = "youtube-music")]
+    YoutubeMusic,
+    Soundcloud,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Youtube => write!(f, "youtube"),
+            Source::YoutubeMusic => write!(f, "youtube-music"),
+            Source::Soundcloud => write!(f, "soundcloud"),
         }
     }
 }
@@ -1,16 +1,25 @@
-use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SpotifyTrack {
+    pub id: String,
     pub name: String,
     pub artists: Vec<String>,
     pub album_name: String,
     pub album_cover: String,
     pub disc_number: i32,
     pub track_number: u32,
+    pub duration_ms: u32,
+    // international standard recording code, the same recording on every platform
+    pub isrc: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SpotifyAlbum {
     pub name: String,
     pub tracks: Vec<SpotifyTrack>,
@@ -18,20 +27,33 @@ pub struct SpotifyAlbum {
     pub cover_url: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SpotifyPlaylist {
     pub name: String,
     pub number_of_songs: u32,
     pub tracks: Vec<SpotifyTrack>,
-    #[allow(dead_code)]
     pub cover_url: Vec<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpotifyArtist {
+    pub name: String,
+    pub genres: Vec<String>,
+    pub followers: u32,
+    pub cover_url: Option<String>,
+    // downloading an artist downloads these
+    pub top_tracks: Vec<SpotifyTrack>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Spotify {
     Album,
     Playlist,
     Track,
+    Artist,
+    // the user's liked songs
+    Library,
 }
 
 impl FromStr for Spotify {
@@ -42,7 +64,251 @@ impl FromStr for Spotify {
             "album" => Ok(Spotify::Album),
             "playlist" => Ok(Spotify::Playlist),
             "track" => Ok(Spotify::Track),
-            _ => Err("Specification: {album, playlist, track}".to_string()),
+            "artist" => Ok(Spotify::Artist),
+            "library" => Ok(Spotify::Library),
+            _ => Err("Specification: {album, playlist, track, artist, library}".to_string()),
+        }
+    }
+}
+
+// what a link given on the command line points to, serialized as its canonical URL
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SpotifyLink {
+    pub kind: Spotify,
+    // empty for the library
+    pub id: String,
+}
+
+impl SpotifyLink {
+    fn new(kind: &str, id: &str) -> Result<Self, String> {
+        let kind = match kind {
+            "collection" => return Ok(SpotifyLink::library()),
+            "album" | "playlist" | "track" | "artist" => Spotify::from_str(kind)?,
+            _ => return Err(format!("Spotify {} links can't be downloaded", kind)),
+        };
+
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("{} isn't a valid spotify id", id));
+        }
+
+        Ok(SpotifyLink {
+            kind,
+            id: id.to_string(),
+        })
+    }
+
+    pub fn library() -> Self {
+        SpotifyLink {
+            kind: Spotify::Library,
+            id: String::new(),
+        }
+    }
+}
+
+impl FromStr for SpotifyLink {
+    type Err = String;
+
+    // https://open.spotify.com/track/<id>?si=..., spotify:track:<id> or "library" for the liked songs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "library" {
+            return Ok(SpotifyLink::library());
+        }
+
+        if let Some(uri) = s.strip_prefix("spotify:") {
+            let parts: Vec<&str> = uri.split(':').collect();
+            return match parts.as_slice() {
+                [kind, id] => SpotifyLink::new(kind, id),
+                // spotify:user:<user>:collection
+                ["user", _, "collection", ..] => Ok(SpotifyLink::library()),
+                _ => Err(format!("{} isn't a spotify URI", s)),
+            };
+        }
+
+        let url = reqwest::Url::parse(s).map_err(|_| format!("{} isn't a spotify link", s))?;
+        if url.host_str() != Some("open.spotify.com") {
+            return Err(format!("{} isn't a spotify link", s));
+        }
+
+        // localized links carry the language first, open.spotify.com/intl-de/album/<id>
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|segment| !segment.is_empty() && !segment.starts_with("intl-"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        match segments.as_slice() {
+            [kind, id, ..] => SpotifyLink::new(kind, id),
+            _ => Err(format!("{} doesn't point to a track, album or playlist", s)),
+        }
+    }
+}
+
+impl fmt::Display for SpotifyLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Spotify::Album => write!(f, "https://open.spotify.com/album/{}", self.id),
+            Spotify::Playlist => write!(f, "https://open.spotify.com/playlist/{}", self.id),
+            Spotify::Track => write!(f, "https://open.spotify.com/track/{}", self.id),
+            Spotify::Artist => write!(f, "https://open.spotify.com/artist/{}", self.id),
+            Spotify::Library => write!(f, "https://open.spotify.com/collection/tracks"),
+        }
+    }
+}
+
+impl TryFrom<String> for SpotifyLink {
+    type Error = String;
+
+    fn try_from(link: String) -> Result<Self, Self::Error> {
+        link.parse()
+    }
+}
+
+impl From<SpotifyLink> for String {
+    fn from(link: SpotifyLink) -> Self {
+        link.to_string()
+    }
+}
+
+// one row of a search, enough to pick it and download it through its link
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpotifySearchResult {
+    pub link: SpotifyLink,
+    pub name: String,
+    // the owner for playlists, empty for artists
+    pub artists: Vec<String>,
+    pub year: Option<String>,
+    pub track_count: Option<u32>,
+}
+
+// a resolved link, with every track it holds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SpotifyEntity {
+    Track(SpotifyTrack),
+    Album(SpotifyAlbum),
+    // also the user's liked songs
+    Playlist(SpotifyPlaylist),
+    Artist(SpotifyArtist),
+}
+
+impl SpotifyEntity {
+    pub fn name(&self) -> &str {
+        match self {
+            SpotifyEntity::Track(track) => &track.name,
+            SpotifyEntity::Album(album) => &album.name,
+            SpotifyEntity::Playlist(playlist) => &playlist.name,
+            SpotifyEntity::Artist(artist) => &artist.name,
+        }
+    }
+
+    pub fn tracks(&self) -> &[SpotifyTrack] {
+        match self {
+            SpotifyEntity::Track(track) => std::slice::from_ref(track),
+            SpotifyEntity::Album(album) => &album.tracks,
+            SpotifyEntity::Playlist(playlist) => &playlist.tracks,
+            SpotifyEntity::Artist(artist) => &artist.top_tracks,
+        }
+    }
+
+    // the number of tracks spotify reports, which counts podcast episodes and unavailable tracks too
+    pub fn number_of_songs(&self) -> usize {
+        match self {
+            SpotifyEntity::Track(_) | SpotifyEntity::Artist(_) => self.tracks().len(),
+            SpotifyEntity::Album(album) => album.number_of_songs as usize,
+            SpotifyEntity::Playlist(playlist) => playlist.number_of_songs as usize,
+        }
+    }
+
+    // false when some tracks of an album or playlist are missing, like podcast episodes or tracks
+    // that aren't available anymore
+    pub fn is_complete(&self) -> bool {
+        self.tracks().len() == self.number_of_songs()
+    }
+
+    // albums and playlists get a directory of their own, single tracks go straight into the path
+    pub fn directory(&self, file_path: &Path) -> PathBuf {
+        match self {
+            SpotifyEntity::Track(_) => file_path.to_path_buf(),
+            SpotifyEntity::Album(album) => file_path.join(&album.name),
+            SpotifyEntity::Playlist(playlist) => file_path.join(&playlist.name),
+            SpotifyEntity::Artist(artist) => file_path.join(&artist.name),
+        }
+    }
+
+    pub fn cover_url(&self) -> Option<&str> {
+        match self {
+            SpotifyEntity::Track(track) => {
+                Some(track.album_cover.as_str()).filter(|url| !url.is_empty())
+            }
+            SpotifyEntity::Album(album) => album.cover_url.as_deref(),
+            SpotifyEntity::Playlist(playlist) => playlist.cover_url.first().map(String::as_str),
+            SpotifyEntity::Artist(artist) => artist.cover_url.as_deref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(link: &str) -> SpotifyLink {
+        link.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_urls_with_a_query_or_a_language() {
+        let link = parse("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc");
+        assert!(matches!(link.kind, Spotify::Track));
+        assert_eq!(link.id, "4uLU6hMCjMI75M1A2tKUQC");
+
+        let link = parse("https://open.spotify.com/intl-de/album/1DFixLWuPkv3KT3TnV35m3");
+        assert!(matches!(link.kind, Spotify::Album));
+        assert_eq!(link.id, "1DFixLWuPkv3KT3TnV35m3");
+    }
+
+    #[test]
+    fn parses_uris() {
+        let link = parse("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M");
+        assert!(matches!(link.kind, Spotify::Playlist));
+        assert_eq!(
+            link.to_string(),
+            "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M"
+        );
+        assert!(matches!(
+            parse(" spotify:artist:0gxyHStUsqpMadRV0Di1Qt ").kind,
+            Spotify::Artist
+        ));
+    }
+
+    #[test]
+    fn parses_every_form_of_the_library() {
+        for link in [
+            "library",
+            "spotify:user:someone:collection",
+            "https://open.spotify.com/collection/tracks",
+        ] {
+            let link = parse(link);
+            assert!(matches!(link.kind, Spotify::Library));
+            assert_eq!(link.id, "");
+        }
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        for link in [
+            "https://example.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            "https://open.spotify.com/show/4rOoJ6Egrf8K2IrywzwOMk",
+            "https://open.spotify.com/track/",
+            "spotify:track:not-an-id",
+            "spotify:track",
+            "not a link",
+        ] {
+            assert!(link.parse::<SpotifyLink>().is_err(), "{}", link);
         }
     }
 }
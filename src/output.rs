@@ -0,0 +1,135 @@
+use crate::plan::PlannedTrack;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{path::PathBuf, sync::OnceLock};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    // colored text for people
+    #[default]
+    Text,
+    // one JSON event per line for scripts
+    Json,
+    // nothing on stdout, for services embedding the library that follow the events instead
+    #[value(skip)]
+    Silent,
+}
+
+// set once in main like the logger, every part of a run reads it
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+pub fn is_json() -> bool {
+    format() == OutputFormat::Json
+}
+
+// progress bars and messages are only drawn for people
+pub fn is_text() -> bool {
+    format() == OutputFormat::Text
+}
+
+// what happened in a run, in the order it happened
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Resolved {
+        link: String,
+        name: String,
+        tracks: usize,
+    },
+    Queued {
+        spotify_id: String,
+        track: String,
+        file: PathBuf,
+    },
+    Planned(PlannedTrack),
+    Matched {
+        spotify_id: String,
+        track: String,
+        // override, cache or search
+        matched_by: String,
+        source: String,
+        title: String,
+        score: f64,
+    },
+    Progress {
+        completed: usize,
+        total: usize,
+    },
+    Downloaded {
+        spotify_id: String,
+        track: String,
+        source: String,
+    },
+    Tagged {
+        spotify_id: String,
+        track: String,
+        file: PathBuf,
+    },
+    Skipped {
+        spotify_id: String,
+        track: String,
+        reason: String,
+    },
+    Failed {
+        // links that couldn't be resolved have no track id
+        spotify_id: Option<String>,
+        track: String,
+        reason: String,
+        // every candidate failed verification and was deleted, nothing was kept
+        flagged: bool,
+    },
+    Exported {
+        link: String,
+        format: String,
+        file: PathBuf,
+    },
+    Summary {
+        downloaded: usize,
+        up_to_date: usize,
+        retagged: usize,
+        flagged: usize,
+        skipped: usize,
+        failed: usize,
+    },
+}
+
+// writes the event to stdout as a single line, only in json mode
+pub fn emit(event: Event) {
+    if !is_json() {
+        return;
+    }
+    match serde_json::to_string(&event) {
+        Ok(line) => println!("{}", line),
+        Err(err) => eprintln!("Event couldn't be serialized: {}", err),
+    }
+}
+
+// text meant for people, left out of the json stream
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::output::is_text() {
+            println!($($arg)*);
+        }
+    };
+}
+
+// questions to the user, moved to stderr so they never mix with the json stream
+#[macro_export]
+macro_rules! prompt {
+    ($($arg:tt)*) => {
+        if $crate::output::is_text() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,119 @@
+use std::{collections::HashMap, fs, path::Path};
+
+// value that marks a track as never to be downloaded
+const SKIP: &str = "skip";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Override {
+    // download exactly this video instead of searching
+    Url(String),
+    Skip,
+}
+
+// hand curated matches keyed by spotify track id, read from a toml or json file such as
+//
+// "4uLU6hMCjMI75M1A2tKUQC" = "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+// "3n3Ppam7vgaVa1iaRUc9Lp" = "skip"
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    entries: HashMap<String, Override>,
+}
+
+impl Overrides {
+    pub fn load(path: &Path) -> Result<Overrides, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read the overrides file {:?}: {}", path, err))?;
+
+        let raw: HashMap<String, String> = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)
+                .map_err(|err| format!("Couldn't parse the overrides file {:?}: {}", path, err))?,
+            Some("toml") => toml::from_str(&contents)
+                .map_err(|err| format!("Couldn't parse the overrides file {:?}: {}", path, err))?,
+            _ => {
+                return Err(format!(
+                    "Overrides file {:?} has to be a .toml or .json file",
+                    path
+                ))
+            }
+        };
+
+        let entries = raw
+            .into_iter()
+            .map(|(id, value)| {
+                let value = if value.eq_ignore_ascii_case(SKIP) {
+                    Override::Skip
+                } else {
+                    Override::Url(value)
+                };
+                (id, value)
+            })
+            .collect();
+
+        Ok(Overrides { entries })
+    }
+
+    pub fn get(&self, spotify_id: &str) -> Option<&Override> {
+        self.entries.get(spotify_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::process;
+
+    fn load(extension: &str, contents: &str) -> Result<Overrides, String> {
+        let path = temp_dir().join(format!("overrides-{}.{}", process::id(), extension));
+        fs::write(&path, contents).unwrap();
+        let overrides = Overrides::load(&path);
+        fs::remove_file(&path).unwrap();
+        overrides
+    }
+
+    #[test]
+    fn loads_urls_and_skips_from_toml() {
+        let overrides = load(
+            "toml",
+            "\"4uLU6hMCjMI75M1A2tKUQC\" = \"https://www.youtube.com/watch?v=dQw4w9WgXcQ\"\n\
+             \"3n3Ppam7vgaVa1iaRUc9Lp\" = \"Skip\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            overrides.get("4uLU6hMCjMI75M1A2tKUQC"),
+            Some(&Override::Url(
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ".to_string()
+            ))
+        );
+        assert_eq!(
+            overrides.get("3n3Ppam7vgaVa1iaRUc9Lp"),
+            Some(&Override::Skip)
+        );
+        assert_eq!(overrides.get("1DFixLWuPkv3KT3TnV35m3"), None);
+    }
+
+    #[test]
+    fn loads_json() {
+        let overrides = load("json", r#"{"3n3Ppam7vgaVa1iaRUc9Lp": "skip"}"#).unwrap();
+        assert_eq!(
+            overrides.get("3n3Ppam7vgaVa1iaRUc9Lp"),
+            Some(&Override::Skip)
+        );
+    }
+
+    #[test]
+    fn rejects_other_extensions_and_invalid_files() {
+        let err = load("yaml", "").unwrap_err();
+        assert!(err.contains("has to be a .toml or .json file"));
+
+        let err = load("json", "[\"skip\"]").unwrap_err();
+        assert!(err.starts_with("Couldn't parse the overrides file"));
+    }
+
+    #[test]
+    fn reports_a_missing_file() {
+        let err = Overrides::load(Path::new("/nonexistent/overrides.toml")).unwrap_err();
+        assert!(err.starts_with("Couldn't read the overrides file"));
+    }
+}
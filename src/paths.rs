@@ -0,0 +1,55 @@
+#[cfg(unix)]
+use std::fs::{set_permissions, Permissions};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::{
+    fs::{create_dir_all, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+const APP_NAME: &str = "yuck_premium";
+
+// per user cache directory, ~/.cache/yuck_premium on linux
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_NAME)
+}
+
+// per user config directory, ~/.config/yuck_premium on linux
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_NAME)
+}
+
+// cached spotify token of an account, ~/.cache/yuck_premium/tokens/<account>.json on linux
+pub fn token_cache_path(account: &str) -> PathBuf {
+    cache_dir().join("tokens").join(format!("{}.json", account))
+}
+
+// tokens grant access to the user's spotify account, so only the owner may read them.
+// the file is created up front since rspotify keeps the permissions of an existing file
+pub fn prepare_private_file(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+        #[cfg(unix)]
+        set_permissions(parent, Permissions::from_mode(0o700))?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)?;
+
+    #[cfg(unix)]
+    set_permissions(path, Permissions::from_mode(0o600))?;
+
+    Ok(())
+}
+
+// log files of the runs, ~/.cache/yuck_premium/logs on linux
+pub fn log_dir() -> PathBuf {
+    cache_dir().join("logs")
+}
@@ -0,0 +1,185 @@
+use crate::{
+    cache::MatchCache,
+    cli::Config,
+    downloader::{create_query, track_path},
+    error::Error,
+    matcher::{search_candidates, Candidate},
+    models::spotify::SpotifyTrack,
+    output::{emit, is_json, Event},
+    overrides::Override,
+    say,
+};
+use colored::Colorize;
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+// what a download of a single track would do, without doing it
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedTrack {
+    pub spotify_id: String,
+    pub name: String,
+    pub artists: String,
+    // override, cache or search
+    pub matched_by: Option<String>,
+    pub source: Option<String>,
+    pub source_title: Option<String>,
+    pub score: Option<f64>,
+    pub file: PathBuf,
+    pub skipped: bool,
+}
+
+pub async fn plan_track(
+    spotify_song: SpotifyTrack,
+    cli_args: &Config,
+    file_path: &Path,
+    cache: &MatchCache,
+) -> PlannedTrack {
+    let file = track_path(file_path, &spotify_song, cli_args);
+
+    let (matched_by, candidate, skipped): (Option<&str>, Option<Candidate>, bool) =
+        match cli_args.overrides.get(&spotify_song.id) {
+            Some(Override::Skip) => (Some("override"), None, true),
+            Some(Override::Url(url)) => (
+                Some("override"),
+                Some(Candidate::pinned(url.clone())),
+                false,
+            ),
+            None => {
+                // the same order as a download: the library, then the cache, then a search
+                let in_library = !cli_args
+                    .source
+                    .local_candidates(&spotify_song)
+                    .await
+                    .is_empty();
+                let cached = cache
+                    .get(&spotify_song.id)
+                    .filter(|_| !cli_args.rematch && !in_library);
+                match cached {
+                    Some(cached) => (Some("cache"), Some(cached.to_candidate()), false),
+                    None => {
+                        let query = create_query(spotify_song.clone());
+                        let candidate =
+                            search_candidates(cli_args.source.as_ref(), query, &spotify_song)
+                                .await
+                                .into_iter()
+                                .next();
+                        (candidate.as_ref().map(|_| "search"), candidate, false)
+                    }
+                }
+            }
+        };
+
+    PlannedTrack {
+        spotify_id: spotify_song.id,
+        name: spotify_song.name,
+        artists: spotify_song.artists.join(", "),
+        matched_by: matched_by.map(str::to_string),
+        source: candidate.as_ref().map(|candidate| candidate.url.clone()),
+        source_title: candidate.as_ref().map(|candidate| candidate.title.clone()),
+        score: candidate.as_ref().map(|candidate| candidate.score),
+        file,
+        skipped,
+    }
+}
+
+// resolves the matches of every track without downloading them
+pub async fn plan_tracks(
+    tracks: Vec<SpotifyTrack>,
+    file_path: PathBuf,
+    cli_args: &Config,
+    cache: &MatchCache,
+) -> Vec<PlannedTrack> {
+    let parallel_searches = cli_args.chunk.unwrap_or(10).max(1) as usize;
+
+    stream::iter(tracks)
+        .map(|song| plan_track(song, cli_args, &file_path, cache))
+        .buffered(parallel_searches)
+        .collect()
+        .await
+}
+
+// prints the plan, or writes it to the plan file
+pub fn dry_run(plan: &[PlannedTrack], cli_args: &Config) -> Result<(), Error> {
+    if is_json() && cli_args.plan_file.is_none() {
+        for planned in plan {
+            emit(Event::Planned(planned.clone()));
+        }
+        return Ok(());
+    }
+
+    match &cli_args.plan_file {
+        Some(plan_file) => {
+            write_plan(plan, plan_file)?;
+            say!("{}", format!("Plan written to {:?}", plan_file).green());
+        }
+        None => print_plan(plan),
+    }
+    Ok(())
+}
+
+pub fn print_plan(plan: &[PlannedTrack]) {
+    say!("{}", "------------Dry Run----------".bright_yellow());
+
+    for planned in plan {
+        say!(
+            "{}",
+            format!("{} - {}", planned.name, planned.artists).bright_blue()
+        );
+
+        if planned.skipped {
+            say!("{}", "    skipped by override".yellow());
+            continue;
+        }
+
+        match (&planned.source, planned.score) {
+            (Some(source), Some(score)) => say!(
+                "    {} ({}, score {:.2})",
+                source,
+                planned.matched_by.as_deref().unwrap_or_default(),
+                score
+            ),
+            _ => say!("{}", "    no match found".red()),
+        }
+        say!("    {}", planned.file.display());
+    }
+}
+
+// the format follows the extension of the plan file
+pub fn write_plan(plan: &[PlannedTrack], plan_file: &Path) -> Result<(), Error> {
+    match plan_file.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => {
+            let contents = serde_json::to_string_pretty(plan)
+                .map_err(|err| Error::Io(format!("Plan couldn't be serialized: {}", err)))?;
+            std::fs::write(plan_file, contents).map_err(|err| {
+                Error::Io(format!(
+                    "Plan couldn't be written to {:?}: {}",
+                    plan_file, err
+                ))
+            })
+        }
+        Some("csv") => {
+            let mut writer = csv::Writer::from_path(plan_file).map_err(|err| {
+                Error::Io(format!(
+                    "Plan couldn't be written to {:?}: {}",
+                    plan_file, err
+                ))
+            })?;
+            for planned in plan {
+                writer
+                    .serialize(planned)
+                    .map_err(|err| Error::Io(format!("Plan couldn't be serialized: {}", err)))?;
+            }
+            writer.flush().map_err(|err| {
+                Error::Io(format!(
+                    "Plan couldn't be written to {:?}: {}",
+                    plan_file, err
+                ))
+            })
+        }
+        _ => Err(Error::Config(format!(
+            "Plan file {:?} has to be a .json or .csv file",
+            plan_file
+        ))),
+    }
+}
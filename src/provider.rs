@@ -0,0 +1,62 @@
+use crate::{
+    cli::Config,
+    error::Error,
+    models::spotify::{Spotify, SpotifyEntity, SpotifyLink, SpotifySearchResult},
+    spotify::SpotifyClients,
+};
+use futures::future::BoxFuture;
+
+// where the tracks, albums and playlists come from. the models are plain data without anything
+// of the catalog they were fetched from, spotify is only one implementation, another catalog or
+// a test double fills them the same way.
+// the futures are boxed so a provider can be swapped at runtime behind a Box<dyn MetadataProvider>
+pub trait MetadataProvider: Send {
+    // fetches whatever the link points to with all of its tracks
+    fn resolve<'a>(
+        &'a mut self,
+        link: &'a SpotifyLink,
+    ) -> BoxFuture<'a, Result<SpotifyEntity, Error>>;
+
+    fn search<'a>(
+        &'a mut self,
+        query: &'a str,
+        kind: Spotify,
+        limit: u32,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>>;
+
+    // the albums of an artist, search offers them when an artist is picked
+    fn artist_albums<'a>(
+        &'a mut self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>>;
+}
+
+impl<P: MetadataProvider + ?Sized> MetadataProvider for Box<P> {
+    fn resolve<'a>(
+        &'a mut self,
+        link: &'a SpotifyLink,
+    ) -> BoxFuture<'a, Result<SpotifyEntity, Error>> {
+        (**self).resolve(link)
+    }
+
+    fn search<'a>(
+        &'a mut self,
+        query: &'a str,
+        kind: Spotify,
+        limit: u32,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>> {
+        (**self).search(query, kind, limit)
+    }
+
+    fn artist_albums<'a>(
+        &'a mut self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>> {
+        (**self).artist_albums(id)
+    }
+}
+
+// the provider of a run unless the caller brings their own, spotify with the run's credentials
+pub fn default_provider(cli_args: &Config) -> Box<dyn MetadataProvider> {
+    Box::new(SpotifyClients::new(cli_args))
+}
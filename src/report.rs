@@ -0,0 +1,184 @@
+use crate::error::Error;
+use crate::models::spotify::{SpotifyLink, SpotifyTrack};
+use crate::output::{emit, is_json, Event};
+use crate::say;
+use colored::Colorize;
+use std::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Clone, Debug)]
+pub enum TrackStatus {
+    Downloaded,
+    // the file was already there, only set by sync
+    UpToDate,
+    Retagged,
+    // every candidate failed verification and was deleted, nothing was kept
+    Flagged(String),
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ReportEntry {
+    pub track: String,
+    pub status: TrackStatus,
+}
+
+pub fn track_label(spotify_song: &SpotifyTrack) -> String {
+    format!(
+        "{} - {}",
+        spotify_song.name,
+        spotify_song.artists.join(", ")
+    )
+}
+
+// outcome of every track in a run, shared between the download tasks
+#[derive(Debug, Default)]
+pub struct RunReport {
+    entries: Mutex<Vec<ReportEntry>>,
+    // every event of the run is sent here too, for services following the run as it happens
+    events: Option<UnboundedSender<Event>>,
+}
+
+impl RunReport {
+    pub fn with_events(events: UnboundedSender<Event>) -> RunReport {
+        RunReport {
+            entries: Mutex::default(),
+            events: Some(events),
+        }
+    }
+
+    // a receiver that went away only means nobody follows the run anymore
+    pub fn emit(&self, event: Event) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event.clone());
+        }
+        emit(event);
+    }
+
+    pub fn record(&self, spotify_song: &SpotifyTrack, status: TrackStatus) {
+        let track = track_label(spotify_song);
+        let spotify_id = spotify_song.id.clone();
+
+        // successes are announced where they happen, with the file they produced
+        match &status {
+            TrackStatus::Downloaded | TrackStatus::Retagged => {}
+            TrackStatus::UpToDate => self.emit(Event::Skipped {
+                spotify_id,
+                track: track.clone(),
+                reason: "Up to date".to_string(),
+            }),
+            TrackStatus::Skipped(reason) => self.emit(Event::Skipped {
+                spotify_id,
+                track: track.clone(),
+                reason: reason.clone(),
+            }),
+            TrackStatus::Flagged(reason) | TrackStatus::Failed(reason) => {
+                self.emit(Event::Failed {
+                    spotify_id: Some(spotify_id),
+                    track: track.clone(),
+                    reason: reason.clone(),
+                    flagged: matches!(status, TrackStatus::Flagged(_)),
+                })
+            }
+        }
+
+        let entry = ReportEntry { track, status };
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    // links that couldn't be resolved show up in the summary like the tracks that failed
+    pub fn record_failed_link(&self, link: &SpotifyLink, reason: String) {
+        self.emit(Event::Failed {
+            spotify_id: None,
+            track: link.to_string(),
+            reason: reason.clone(),
+            flagged: false,
+        });
+
+        let entry = ReportEntry {
+            track: link.to_string(),
+            status: TrackStatus::Failed(reason),
+        };
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    pub fn entries(&self) -> Vec<ReportEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    // a run with failed links or tracks fails as a whole, even if the rest went through. flagged
+    // tracks count as failed, nothing was written for them. a run where nothing went through
+    // isn't partial
+    pub fn outcome(&self) -> Result<(), Error> {
+        let entries = self.entries();
+        let failed = entries
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.status,
+                    TrackStatus::Failed(_) | TrackStatus::Flagged(_)
+                )
+            })
+            .count();
+
+        if failed == 0 {
+            return Ok(());
+        }
+        if failed == entries.len() {
+            return Err(Error::Download(format!("All {} tracks failed", failed)));
+        }
+        Err(Error::PartialFailure {
+            failed,
+            total: entries.len(),
+        })
+    }
+
+    pub fn print_summary(&self) {
+        let entries = self.entries();
+        let count = |wanted: fn(&TrackStatus) -> bool| {
+            entries.iter().filter(|entry| wanted(&entry.status)).count()
+        };
+        let downloaded = count(|status| matches!(status, TrackStatus::Downloaded));
+        let up_to_date = count(|status| matches!(status, TrackStatus::UpToDate));
+        let retagged = count(|status| matches!(status, TrackStatus::Retagged));
+
+        if is_json() {
+            self.emit(Event::Summary {
+                downloaded,
+                up_to_date,
+                retagged,
+                flagged: count(|status| matches!(status, TrackStatus::Flagged(_))),
+                skipped: count(|status| matches!(status, TrackStatus::Skipped(_))),
+                failed: count(|status| matches!(status, TrackStatus::Failed(_))),
+            });
+            return;
+        }
+
+        say!("{}", "------------Run Summary----------".bright_yellow());
+        say!("{}", format!("Downloaded: {}", downloaded).green());
+        if up_to_date > 0 {
+            say!("{}", format!("Up to date: {}", up_to_date).green());
+        }
+        if retagged > 0 {
+            say!("{}", format!("Retagged: {}", retagged).green());
+        }
+
+        for entry in &entries {
+            match &entry.status {
+                TrackStatus::Downloaded | TrackStatus::UpToDate | TrackStatus::Retagged => {}
+                TrackStatus::Flagged(reason) => say!(
+                    "{}",
+                    format!("Flagged: {} ({})", entry.track, reason).yellow()
+                ),
+                TrackStatus::Skipped(reason) => say!(
+                    "{}",
+                    format!("Skipped: {} ({})", entry.track, reason).bright_blue()
+                ),
+                TrackStatus::Failed(reason) => {
+                    say!("{}", format!("Failed: {} ({})", entry.track, reason).red())
+                }
+            }
+        }
+    }
+}
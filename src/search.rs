@@ -0,0 +1,135 @@
+use crate::models::spotify::SpotifySearchResult;
+use crate::output::is_text;
+use crate::prompt;
+use colored::Colorize;
+use std::io::{stdin, stdout, IsTerminal, Write};
+
+const NAME_WIDTH: usize = 40;
+const ARTISTS_WIDTH: usize = 30;
+
+// cuts long names so the columns stay aligned
+pub fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return format!("{:width$}", text, width = width);
+    }
+    let cut: String = text.chars().take(width - 1).collect();
+    format!("{}…", cut)
+}
+
+pub fn print_results(results: &[SpotifySearchResult]) {
+    prompt!(
+        "{}",
+        format!(
+            "{:>3}  {}  {}  {:4}  {:>6}",
+            "#",
+            fit("Name", NAME_WIDTH),
+            fit("Artists", ARTISTS_WIDTH),
+            "Year",
+            "Tracks"
+        )
+        .bright_yellow()
+    );
+
+    for (number, result) in results.iter().enumerate() {
+        prompt!(
+            "{:>3}  {}  {}  {:4}  {:>6}",
+            number + 1,
+            fit(&result.name, NAME_WIDTH).bright_blue(),
+            fit(&result.artists.join(", "), ARTISTS_WIDTH),
+            result.year.as_deref().unwrap_or("-"),
+            result
+                .track_count
+                .map(|count| count.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+    }
+}
+
+// "1 3 5-7", "2,4" or "all", returns the indices of the picked results
+pub fn parse_selection(input: &str, len: usize) -> Result<Vec<usize>, String> {
+    let input = input.trim();
+    if input == "all" {
+        return Ok((0..len).collect());
+    }
+
+    let mut picked: Vec<usize> = Vec::new();
+    for part in input.split([',', ' ']).filter(|part| !part.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (part, part),
+        };
+        let parse = |number: &str| {
+            number
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|number| (1..=len).contains(number))
+                .ok_or_else(|| format!("{} isn't a number between 1 and {}", number, len))
+        };
+
+        for number in parse(start)?..=parse(end)? {
+            if !picked.contains(&(number - 1)) {
+                picked.push(number - 1);
+            }
+        }
+    }
+
+    Ok(picked)
+}
+
+// asks until the selection parses, an empty line picks nothing.
+// without a terminal there's nobody to ask, the results are only listed
+pub fn prompt_selection(len: usize) -> Vec<usize> {
+    if len == 0 || !stdin().is_terminal() {
+        return Vec::new();
+    }
+
+    loop {
+        let question = "Pick the results to download (e.g. 1 3 5-7, all, empty to quit): ";
+        if is_text() {
+            print!("{}", question);
+            let _ = stdout().flush();
+        } else {
+            eprint!("{}", question);
+        }
+
+        let mut input = String::new();
+        if stdin().read_line(&mut input).is_err() {
+            return Vec::new();
+        }
+
+        match parse_selection(&input, len) {
+            Ok(picked) => return picked,
+            Err(err) => prompt!("{}", err.red()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_numbers_and_ranges() {
+        assert_eq!(parse_selection("1 3 5-7", 10), Ok(vec![0, 2, 4, 5, 6]));
+        assert_eq!(parse_selection("2,4", 10), Ok(vec![1, 3]));
+    }
+
+    #[test]
+    fn picks_every_result_once() {
+        assert_eq!(parse_selection("all", 3), Ok(vec![0, 1, 2]));
+        assert_eq!(parse_selection("2 1-3 2", 3), Ok(vec![1, 0, 2]));
+    }
+
+    #[test]
+    fn picks_nothing_from_an_empty_line() {
+        assert_eq!(parse_selection("  ", 3), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_numbers_out_of_range() {
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("2-4", 3).is_err());
+        assert!(parse_selection("x", 3).is_err());
+    }
+}
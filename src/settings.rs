@@ -0,0 +1,363 @@
+use crate::auth::SpotifyCredentials;
+use crate::models::cli::{Bitrate, Codec, Source};
+use crate::paths::config_dir;
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "config.toml";
+
+// settings shared by the config file, the environment and the command line.
+// every layer only fills in what it sets, later layers win: file < environment < flags
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_uri: Option<String>,
+    // every track is written in each of the codecs, the first one is where sync and retag look
+    #[serde(deserialize_with = "one_or_many")]
+    pub codec: Option<Vec<Codec>>,
+    pub bitrate: Option<Bitrate>,
+    pub output_template: Option<String>,
+    pub chunk: Option<u32>,
+    pub path: Option<PathBuf>,
+    pub overrides: Option<PathBuf>,
+    pub account: Option<String>,
+    pub source: Option<Source>,
+    pub library: Option<PathBuf>,
+    // library files already in the codec are linked into the path instead of copied
+    pub link: Option<bool>,
+    // longest side of the embedded cover in pixels, 0 leaves it out
+    pub cover_size: Option<u32>,
+    // what a codec writes differently from the settings above, as [formats.opus] in the config file
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub formats: BTreeMap<Codec, FormatSettings>,
+}
+
+// the bitrate, path and cover size of one codec when it is written next to others
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatSettings {
+    pub bitrate: Option<Bitrate>,
+    pub path: Option<PathBuf>,
+    pub cover_size: Option<u32>,
+}
+
+impl FormatSettings {
+    // fields set in other take precedence
+    fn merge(self, other: FormatSettings) -> FormatSettings {
+        FormatSettings {
+            bitrate: other.bitrate.or(self.bitrate),
+            path: other.path.or(self.path),
+            cover_size: other.cover_size.or(self.cover_size),
+        }
+    }
+}
+
+// codec = "flac" still reads as the one codec it was before there could be more
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Codec>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Codec),
+        Many(Vec<Codec>),
+    }
+
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(codec) => vec![codec],
+        OneOrMany::Many(codecs) => codecs,
+    }))
+}
+
+// environment variable of every setting, credentials keep the names rspotify uses
+pub const ENV_VARS: &[(&str, &str)] = &[
+    ("client_id", "RSPOTIFY_CLIENT_ID"),
+    ("client_secret", "RSPOTIFY_CLIENT_SECRET"),
+    ("redirect_uri", "RSPOTIFY_REDIRECT_URI"),
+    ("codec", "YUCK_CODEC"),
+    ("bitrate", "YUCK_BITRATE"),
+    ("output_template", "YUCK_OUTPUT_TEMPLATE"),
+    ("chunk", "YUCK_CHUNK"),
+    ("path", "YUCK_PATH"),
+    ("overrides", "YUCK_OVERRIDES"),
+    ("account", "YUCK_ACCOUNT"),
+    ("source", "YUCK_SOURCE"),
+    ("library", "YUCK_LIBRARY"),
+    ("link", "YUCK_LINK"),
+    ("cover_size", "YUCK_COVER_SIZE"),
+];
+
+// points to another config file than the one in the config dir
+pub const CONFIG_ENV_VAR: &str = "YUCK_CONFIG";
+
+pub fn default_config_path() -> PathBuf {
+    config_dir().join(CONFIG_FILE)
+}
+
+fn env_var(setting: &str) -> Option<String> {
+    ENV_VARS
+        .iter()
+        .find(|(name, _)| *name == setting)
+        .and_then(|(_, var)| std::env::var(var).ok())
+        .filter(|value| !value.is_empty())
+}
+
+// the error a missing setting is reported with, naming every place it can be set in
+pub fn missing_setting(setting: &str) -> String {
+    let var = ENV_VARS
+        .iter()
+        .find(|(name, _)| *name == setting)
+        .map(|(_, var)| *var)
+        .unwrap_or_default();
+
+    format!(
+        "Missing setting {}: set it in {:?} or the {} environment variable",
+        setting,
+        default_config_path(),
+        var
+    )
+}
+
+// the config file given with --config or YUCK_CONFIG, which has to exist
+fn explicit_config_path(config_file: Option<&Path>) -> Option<PathBuf> {
+    config_file
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var(CONFIG_ENV_VAR).ok().map(PathBuf::from))
+}
+
+pub fn config_path(config_file: Option<&Path>) -> PathBuf {
+    explicit_config_path(config_file).unwrap_or_else(default_config_path)
+}
+
+impl Settings {
+    // the config file from --config, YUCK_CONFIG or the config dir, overlaid with the environment
+    pub fn load(config_file: Option<&Path>) -> Result<Settings, String> {
+        let file = match explicit_config_path(config_file) {
+            Some(path) => Settings::from_file(&path)?,
+            None => {
+                let path = default_config_path();
+                if path.exists() {
+                    Settings::from_file(&path)?
+                } else {
+                    Settings::default()
+                }
+            }
+        };
+
+        Ok(file.merge(Settings::from_env()?))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Settings, String> {
+        let contents = read_to_string(path)
+            .map_err(|err| format!("Couldn't read the config file {:?}: {}", path, err))?;
+
+        toml::from_str(&contents)
+            .map_err(|err| format!("Couldn't parse the config file {:?}: {}", path, err))
+    }
+
+    pub fn from_env() -> Result<Settings, String> {
+        let chunk = match env_var("chunk") {
+            Some(chunk) => Some(
+                chunk
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|chunk| *chunk > 0)
+                    .ok_or_else(|| {
+                        format!("YUCK_CHUNK has to be a number above 0, got {}", chunk)
+                    })?,
+            ),
+            None => None,
+        };
+        let codec = match env_var("codec") {
+            Some(codecs) => Some(
+                codecs
+                    .split(',')
+                    .map(|codec| {
+                        Codec::from_str(codec.trim(), true)
+                            .map_err(|_| format!("YUCK_CODEC {} isn't a supported codec", codec))
+                    })
+                    .collect::<Result<Vec<Codec>, String>>()?,
+            ),
+            None => None,
+        };
+        let bitrate = match env_var("bitrate") {
+            Some(bitrate) => Some(
+                Bitrate::from_str(&bitrate, true)
+                    .map_err(|_| format!("YUCK_BITRATE {} isn't a supported bitrate", bitrate))?,
+            ),
+            None => None,
+        };
+        let source = match env_var("source") {
+            Some(source) => Some(
+                Source::from_str(&source, true)
+                    .map_err(|_| format!("YUCK_SOURCE {} isn't a supported source", source))?,
+            ),
+            None => None,
+        };
+
+        let link = match env_var("link") {
+            Some(link) => Some(
+                link.parse::<bool>()
+                    .map_err(|_| format!("YUCK_LINK has to be true or false, got {}", link))?,
+            ),
+            None => None,
+        };
+        let cover_size = match env_var("cover_size") {
+            Some(size) => Some(size.parse::<u32>().map_err(|_| {
+                format!("YUCK_COVER_SIZE has to be a number of pixels, got {}", size)
+            })?),
+            None => None,
+        };
+
+        Ok(Settings {
+            client_id: env_var("client_id"),
+            client_secret: env_var("client_secret"),
+            redirect_uri: env_var("redirect_uri"),
+            codec,
+            bitrate,
+            output_template: env_var("output_template"),
+            chunk,
+            path: env_var("path").map(PathBuf::from),
+            overrides: env_var("overrides").map(PathBuf::from),
+            account: env_var("account"),
+            source,
+            library: env_var("library").map(PathBuf::from),
+            link,
+            cover_size,
+            formats: BTreeMap::new(),
+        })
+    }
+
+    // fields set in other take precedence
+    pub fn merge(self, other: Settings) -> Settings {
+        Settings {
+            client_id: other.client_id.or(self.client_id),
+            client_secret: other.client_secret.or(self.client_secret),
+            redirect_uri: other.redirect_uri.or(self.redirect_uri),
+            codec: other.codec.or(self.codec),
+            bitrate: other.bitrate.or(self.bitrate),
+            output_template: other.output_template.or(self.output_template),
+            chunk: other.chunk.or(self.chunk),
+            path: other.path.or(self.path),
+            overrides: other.overrides.or(self.overrides),
+            account: other.account.or(self.account),
+            source: other.source.or(self.source),
+            library: other.library.or(self.library),
+            link: other.link.or(self.link),
+            cover_size: other.cover_size.or(self.cover_size),
+            formats: merge_formats(self.formats, other.formats),
+        }
+    }
+
+    // bitrate, path and cover size of the codec, falling back to the ones of every codec
+    pub fn format(&self, codec: Codec) -> FormatSettings {
+        let shared = FormatSettings {
+            bitrate: self.bitrate,
+            path: self.path.clone(),
+            cover_size: self.cover_size,
+        };
+        match self.formats.get(&codec) {
+            Some(format) => shared.merge(format.clone()),
+            None => shared,
+        }
+    }
+
+    pub fn credentials(&self) -> Result<SpotifyCredentials, String> {
+        Ok(SpotifyCredentials {
+            client_id: self
+                .client_id
+                .clone()
+                .ok_or_else(|| missing_setting("client_id"))?,
+            client_secret: self.client_secret.clone(),
+            redirect_uri: self.redirect_uri.clone(),
+        })
+    }
+}
+
+// a codec set in both keeps the fields of other and the rest of base
+fn merge_formats(
+    mut base: BTreeMap<Codec, FormatSettings>,
+    other: BTreeMap<Codec, FormatSettings>,
+) -> BTreeMap<Codec, FormatSettings> {
+    for (codec, format) in other {
+        let merged = base.remove(&codec).unwrap_or_default().merge(format);
+        base.insert(codec, merged);
+    }
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Settings {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn later_layers_win_and_unset_fields_fall_through() {
+        let file = parse(
+            "client_id = \"file\"\n\
+             bitrate = \"320\"\n\
+             path = \"/music\"\n\
+             chunk = 10\n",
+        );
+        let env = Settings {
+            client_id: Some("env".to_string()),
+            chunk: Some(20),
+            ..Settings::default()
+        };
+        let flags = Settings {
+            chunk: Some(30),
+            ..Settings::default()
+        };
+
+        let settings = file.merge(env).merge(flags);
+
+        assert_eq!(settings.client_id.as_deref(), Some("env"));
+        assert_eq!(settings.chunk, Some(30));
+        assert!(matches!(settings.bitrate, Some(Bitrate::High)));
+        assert_eq!(settings.path, Some(PathBuf::from("/music")));
+        assert_eq!(settings.account, None);
+    }
+
+    #[test]
+    fn merges_the_formats_of_a_codec_field_by_field() {
+        let file = parse(
+            "path = \"/music\"\n\
+             cover_size = 600\n\
+             [formats.opus]\n\
+             path = \"/phone\"\n\
+             cover_size = 300\n",
+        );
+        let flags = parse("[formats.opus]\ncover_size = 0\n");
+
+        let settings = file.merge(flags);
+
+        let opus = settings.format(Codec::Opus);
+        assert_eq!(opus.path, Some(PathBuf::from("/phone")));
+        assert_eq!(opus.cover_size, Some(0));
+        let flac = settings.format(Codec::Flac);
+        assert_eq!(flac.path, Some(PathBuf::from("/music")));
+        assert_eq!(flac.cover_size, Some(600));
+    }
+
+    #[test]
+    fn reads_one_codec_or_a_list() {
+        assert_eq!(parse("codec = \"flac\"").codec, Some(vec![Codec::Flac]));
+        assert_eq!(
+            parse("codec = [\"flac\", \"opus\"]").codec,
+            Some(vec![Codec::Flac, Codec::Opus])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(toml::from_str::<Settings>("bitrates = \"320\"").is_err());
+    }
+}
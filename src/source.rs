@@ -0,0 +1,263 @@
+use crate::{
+    error::Error,
+    matcher::{Candidate, SEARCH_CANDIDATES},
+    models::{cli::Source, spotify::SpotifyTrack},
+};
+use futures::future::BoxFuture;
+use log::info;
+use reqwest::Url;
+use std::{
+    fmt,
+    fs::{read_dir, remove_file},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use youtube_dl::{SearchOptions, YoutubeDl};
+
+// where the audio of a track is searched for and downloaded from. the matcher scores and orders
+// the candidates, a source only has to find them and fetch the one that is picked
+pub trait AudioSource: fmt::Debug + Send + Sync {
+    // shown in the logs, the name it is chosen by with --source
+    fn name(&self) -> &'static str;
+
+    // the track is there for sources that can match it by more than the query, like its isrc
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Result<Vec<Candidate>, Error>>;
+
+    // fetches the candidate's audio as the source has it, the downloader transcodes it into the
+    // codec of the run. raw is where a download goes, the source adds the extension
+    fn download<'a>(
+        &'a self,
+        candidate: &'a Candidate,
+        raw: &'a Path,
+    ) -> BoxFuture<'a, Result<SourceAudio, Error>>;
+
+    // files the source already has for the track, like those of a local library. they win over
+    // a match cached from an earlier run
+    fn local_candidates<'a>(
+        &'a self,
+        _spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Vec<Candidate>> {
+        Box::pin(async { Vec::new() })
+    }
+
+    // searched when every one of the local candidates was rejected
+    fn fallback(&self) -> Option<&dyn AudioSource> {
+        None
+    }
+}
+
+// the audio of a candidate before it is transcoded
+#[derive(Clone, Debug)]
+pub struct SourceAudio {
+    pub path: PathBuf,
+    // removed once transcoded, files the user owns are only read
+    pub temporary: bool,
+}
+
+impl Source {
+    pub fn backend(self) -> Arc<dyn AudioSource> {
+        match self {
+            Source::Youtube => Arc::new(YouTube),
+            Source::YoutubeMusic => Arc::new(YouTubeMusic),
+            Source::Soundcloud => Arc::new(SoundCloud),
+        }
+    }
+}
+
+// the default, searches youtube with yt-dlp
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YouTube;
+
+impl AudioSource for YouTube {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        _spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Result<Vec<Candidate>, Error>> {
+        Box::pin(yt_dlp_search(
+            YoutubeDl::search_for(&SearchOptions::youtube(query).with_count(SEARCH_CANDIDATES)),
+            "https://www.youtube.com/watch?v=",
+        ))
+    }
+
+    fn download<'a>(
+        &'a self,
+        candidate: &'a Candidate,
+        raw: &'a Path,
+    ) -> BoxFuture<'a, Result<SourceAudio, Error>> {
+        Box::pin(yt_dlp_download(&candidate.url, raw))
+    }
+}
+
+const YOUTUBE_MUSIC_SEARCH: &str = "https://music.youtube.com/search";
+
+// youtube music, whose songs are the album versions spotify lists, without the intros and skits
+// of music videos. videos are only searched when there is no song at all
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YouTubeMusic;
+
+impl YouTubeMusic {
+    // a section of the search page, yt-dlp lists only its results
+    fn search_url(query: &str, section: &str) -> Result<String, Error> {
+        let mut url = Url::parse_with_params(YOUTUBE_MUSIC_SEARCH, &[("q", query)])
+            .map_err(|err| Error::Search(format!("{} can't be searched for: {}", query, err)))?;
+        url.set_fragment(Some(section));
+        Ok(url.to_string())
+    }
+
+    async fn search_section(query: &str, section: &str) -> Result<Vec<Candidate>, Error> {
+        let mut client = YoutubeDl::new(YouTubeMusic::search_url(query, section)?);
+        client
+            .extra_arg("--playlist-end")
+            .extra_arg(SEARCH_CANDIDATES.to_string());
+        yt_dlp_search(client, "https://music.youtube.com/watch?v=").await
+    }
+}
+
+impl AudioSource for YouTubeMusic {
+    fn name(&self) -> &'static str {
+        "youtube-music"
+    }
+
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        _spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Result<Vec<Candidate>, Error>> {
+        Box::pin(async move {
+            let songs = YouTubeMusic::search_section(query, "songs").await?;
+            if !songs.is_empty() {
+                return Ok(songs);
+            }
+            info!("No songs on youtube music for {}, searching videos", query);
+            YouTubeMusic::search_section(query, "videos").await
+        })
+    }
+
+    fn download<'a>(
+        &'a self,
+        candidate: &'a Candidate,
+        raw: &'a Path,
+    ) -> BoxFuture<'a, Result<SourceAudio, Error>> {
+        Box::pin(yt_dlp_download(&candidate.url, raw))
+    }
+}
+
+// soundcloud through yt-dlp's scsearch, for tracks that never made it to youtube
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SoundCloud;
+
+impl AudioSource for SoundCloud {
+    fn name(&self) -> &'static str {
+        "soundcloud"
+    }
+
+    fn search<'a>(
+        &'a self,
+        query: &'a str,
+        _spotify_song: &'a SpotifyTrack,
+    ) -> BoxFuture<'a, Result<Vec<Candidate>, Error>> {
+        Box::pin(yt_dlp_search(
+            YoutubeDl::search_for(&SearchOptions::soundcloud(query).with_count(SEARCH_CANDIDATES)),
+            "https://api.soundcloud.com/tracks/",
+        ))
+    }
+
+    fn download<'a>(
+        &'a self,
+        candidate: &'a Candidate,
+        raw: &'a Path,
+    ) -> BoxFuture<'a, Result<SourceAudio, Error>> {
+        Box::pin(yt_dlp_download(&candidate.url, raw))
+    }
+}
+
+// the results of a yt-dlp search, unscored. flat results may only carry the id, the url is
+// then put together from the base of the site
+async fn yt_dlp_search(mut client: YoutubeDl, base_url: &str) -> Result<Vec<Candidate>, Error> {
+    let output = client
+        .flat_playlist(true)
+        .run_async()
+        .await
+        .map_err(|err| Error::Search(err.to_string()))?;
+
+    let entries = output
+        .into_playlist()
+        .and_then(|playlist| playlist.entries)
+        .unwrap_or_default();
+
+    Ok(entries
+        .into_iter()
+        .map(|video| {
+            let url = video
+                .webpage_url
+                .or(video.url)
+                .unwrap_or_else(|| format!("{}{}", base_url, video.id));
+
+            Candidate {
+                id: video.id,
+                url,
+                title: video.title.unwrap_or_default(),
+                duration: video.duration.and_then(|duration| duration.as_f64()),
+                score: 0.0,
+                pinned: false,
+            }
+        })
+        .collect())
+}
+
+// files of an earlier download of the same raw path, whatever their extension
+fn raw_files(directory: &Path, name: &str) -> Vec<PathBuf> {
+    let prefix = format!("{}.", name);
+    read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| file_name.starts_with(&prefix))
+        })
+        .collect()
+}
+
+// the best audio stream as it is, yt-dlp picks the extension of its container
+async fn yt_dlp_download(url: &str, raw: &Path) -> Result<SourceAudio, Error> {
+    let directory = raw
+        .parent()
+        .ok_or_else(|| Error::Io(format!("{:?} has no parent directory", raw)))?;
+    let name = raw
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::Io(format!("{:?} isn't a valid file name", raw)))?;
+
+    // a leftover of an interrupted run would make yt-dlp skip the download
+    for leftover in raw_files(directory, name) {
+        let _ = remove_file(leftover);
+    }
+
+    YoutubeDl::new(url)
+        .format("bestaudio/best")
+        .output_template(format!("{}.%(ext)s", name))
+        .download_to_async(directory)
+        .await
+        .map_err(|err| Error::Download(format!("{} couldn't be downloaded: {}", url, err)))?;
+
+    // yt-dlp doesn't report a failed download through its exit status here, the file is the only proof
+    raw_files(directory, name)
+        .into_iter()
+        .find(|path| path.extension().and_then(|ext| ext.to_str()) != Some("part"))
+        .map(|path| SourceAudio {
+            path,
+            temporary: true,
+        })
+        .ok_or_else(|| Error::Download(format!("{} didn't produce an audio file", url)))
+}
@@ -1,31 +1,51 @@
-use crate::models::spotify::{SpotifyAlbum, SpotifyPlaylist, SpotifyTrack};
-use log::error;
+use crate::auth::{public_client, user_authorization, SpotifyCredentials};
+use crate::cli::Config as CliConfig;
+use crate::error::Error;
+use crate::models::spotify::{
+    Spotify, SpotifyAlbum, SpotifyArtist, SpotifyEntity, SpotifyLink, SpotifyPlaylist,
+    SpotifySearchResult, SpotifyTrack,
+};
+use crate::provider::MetadataProvider;
+use futures::{future::BoxFuture, TryStreamExt};
+use log::{debug, error, info};
 use rspotify::{
-    clients::BaseClient,
+    clients::{BaseClient, OAuthClient},
     model::{
-        image::Image, AlbumId, FullTrack, PlayableItem, PlaylistId, PlaylistItem, SimplifiedTrack,
-        TrackId,
+        image::Image, AlbumId, AlbumType, ArtistId, Country, FullTrack, Market, PlayableItem,
+        PlaylistId, PlaylistItem, SavedTrack, SearchResult, SearchType, SimplifiedAlbum,
+        SimplifiedTrack, TrackId,
     },
-    AuthCodeSpotify,
+    prelude::Id,
+    AuthCodePkceSpotify, AuthCodeSpotify, ClientCredsSpotify,
 };
+use std::collections::HashMap;
 
 pub async fn get_track_details(
     spotify_id: String,
-    client: &AuthCodeSpotify,
-) -> Option<SpotifyTrack> {
-    let id = match TrackId::from_id(spotify_id) {
-        Ok(id) => Ok(id),
-        Err(err) => Err(format!("Couldn't parse the given track id: {}", err)),
-    }
-    .unwrap();
+    client: &impl BaseClient,
+) -> Result<SpotifyTrack, Error> {
+    let id = TrackId::from_id(spotify_id.clone()).map_err(|err| {
+        Error::InvalidId(format!(
+            "Couldn't parse the track id {}: {}",
+            spotify_id, err
+        ))
+    })?;
 
     // returns a FullTrack
-    let track = client.track(id, None).await.unwrap();
+    let track = client
+        .track(id, None)
+        .await
+        .map_err(|err| Error::spotify(&format!("Track {} couldn't be fetched", spotify_id), err))?;
+
+    Ok(spotify_track_from_full(track))
+}
 
+fn spotify_track_from_full(track: FullTrack) -> SpotifyTrack {
     // filter the images and return the image with dimensions 640 * 640
     let album_cover = get_album_cover_url(&track);
 
-    let song = SpotifyTrack {
+    SpotifyTrack {
+        id: track_id(&track.id),
         name: track.name,
         album_name: track.album.name,
         album_cover,
@@ -36,8 +56,16 @@ pub async fn get_track_details(
             .collect(),
         disc_number: track.disc_number,
         track_number: track.track_number,
-    };
-    Some(song)
+        duration_ms: track.duration.num_milliseconds() as u32,
+        isrc: track.external_ids.get("isrc").cloned(),
+    }
+}
+
+// local files in playlists have no spotify id
+fn track_id(id: &Option<TrackId>) -> String {
+    id.as_ref()
+        .map(|id| id.id().to_string())
+        .unwrap_or_default()
 }
 
 fn get_album_cover_url(track: &FullTrack) -> String {
@@ -45,18 +73,14 @@ fn get_album_cover_url(track: &FullTrack) -> String {
         .album
         .images
         .iter()
-        .filter(|image| image.width.clone() == Some(640) && image.height.clone() == Some(640))
+        .filter(|image| image.width == Some(640) && image.height == Some(640))
         .cloned()
         .collect::<Vec<Image>>();
 
-    let mut album_cover = String::new();
-
-    for image in album_cover_uri {
-        album_cover = image.url.clone();
-        break;
-    }
-
-    album_cover
+    album_cover_uri
+        .first()
+        .map(|image| image.url.clone())
+        .unwrap_or_default()
 }
 
 fn get_album_cover_url_for_simplified_track(track: &SimplifiedTrack) -> String {
@@ -69,41 +93,53 @@ fn get_album_cover_url_for_simplified_track(track: &SimplifiedTrack) -> String {
     }
     .images
     .iter()
-    .filter(|image| image.width.clone() == Some(640) && image.height.clone() == Some(640))
+    .filter(|image| image.width == Some(640) && image.height == Some(640))
     .cloned()
     .collect::<Vec<Image>>();
 
-    let mut album_cover = String::new();
-
-    for image in album_cover_uri {
-        album_cover = image.url.clone();
-        break;
-    }
-
-    album_cover
+    album_cover_uri
+        .first()
+        .map(|image| image.url.clone())
+        .unwrap_or_default()
 }
 
 pub async fn get_album_details(
     spotify_id: String,
-    client: &AuthCodeSpotify,
-) -> Option<SpotifyAlbum> {
-    let album_id = match AlbumId::from_id(spotify_id) {
-        Ok(id) => Ok(id),
-        Err(err) => Err(format!("Couldn't parse the given album id: {}", err)),
-    }
-    .unwrap();
+    client: &impl BaseClient,
+) -> Result<SpotifyAlbum, Error> {
+    let album_id = AlbumId::from_id(spotify_id.clone()).map_err(|err| {
+        Error::InvalidId(format!(
+            "Couldn't parse the album id {}: {}",
+            spotify_id, err
+        ))
+    })?;
 
     let album = client
-        .album(album_id, None)
+        .album(album_id.clone(), None)
         .await
-        .map_err(|err| println!("\n\nError: {:?}", err));
-    // println!("album tracks: {:?}", album.clone().unwrap().tracks.items);
-    let mut tracks: Vec<SpotifyTrack> =
-        Vec::with_capacity(album.clone().unwrap().tracks.total as usize);
+        .map_err(|err| Error::spotify(&format!("Album {} couldn't be fetched", spotify_id), err))?;
 
-    for track in album.clone().unwrap().tracks.items {
+    // the album only carries the first 50 tracks, the rest are fetched page by page
+    let items = if album.tracks.next.is_none() {
+        album.tracks.items
+    } else {
+        client
+            .album_track(album_id, None)
+            .try_collect::<Vec<SimplifiedTrack>>()
+            .await
+            .map_err(|err| {
+                Error::spotify(
+                    &format!("Tracks of album {} couldn't be fetched", spotify_id),
+                    err,
+                )
+            })?
+    };
+    let mut tracks: Vec<SpotifyTrack> = Vec::with_capacity(items.len());
+
+    for track in items {
         let album_cover = get_album_cover_url_for_simplified_track(&track);
         tracks.push(SpotifyTrack {
+            id: track_id(&track.id),
             name: track.name,
             artists: track
                 .artists
@@ -112,24 +148,45 @@ pub async fn get_album_details(
                 .collect(),
             disc_number: track.disc_number,
             track_number: track.track_number,
+            duration_ms: track.duration.num_milliseconds() as u32,
+            isrc: None,
             album_cover,
-            album_name: album.clone().unwrap().name,
+            album_name: album.name.clone(),
         });
     }
+    add_isrcs(&mut tracks, client).await;
 
-    Some(SpotifyAlbum {
-        name: album.clone().unwrap().name,
+    Ok(SpotifyAlbum {
+        name: album.name,
         tracks,
-        number_of_songs: album.clone().unwrap().tracks.total,
-        cover_url: album
-            .clone()
-            .unwrap()
-            .images
-            .first()
-            .map(|image| image.url.clone()),
+        number_of_songs: album.tracks.total,
+        cover_url: album.images.first().map(|image| image.url.clone()),
     })
 }
 
+// simplified tracks carry no external ids, the full tracks are fetched for them fifty at a time
+async fn add_isrcs(tracks: &mut [SpotifyTrack], client: &impl BaseClient) {
+    let ids: Vec<TrackId<'static>> = tracks
+        .iter()
+        .filter_map(|track| TrackId::from_id(track.id.clone()).ok())
+        .collect();
+    let mut isrcs: HashMap<String, String> = HashMap::new();
+
+    for chunk in ids.chunks(50) {
+        match client.tracks(chunk.to_vec(), None).await {
+            Ok(full_tracks) => isrcs.extend(full_tracks.into_iter().filter_map(|track| {
+                let id = track.id.as_ref()?.id().to_string();
+                Some((id, track.external_ids.get("isrc")?.clone()))
+            })),
+            Err(err) => error!("Error while fetching the ISRCs of the tracks: {}", err),
+        }
+    }
+
+    for track in tracks {
+        track.isrc = isrcs.remove(&track.id);
+    }
+}
+
 pub fn who_loves_podcasts_anyways(playable_items: Vec<PlaylistItem>) -> Vec<SpotifyTrack> {
     let mut tracks: Vec<SpotifyTrack> = Vec::new();
 
@@ -143,20 +200,8 @@ pub fn who_loves_podcasts_anyways(playable_items: Vec<PlaylistItem>) -> Vec<Spot
         let PlayableItem::Track(track) = song else {
             continue;
         };
-        let album_cover = get_album_cover_url(&track);
 
-        tracks.push(SpotifyTrack {
-            name: track.name,
-            artists: track
-                .artists
-                .iter()
-                .map(|artist| artist.name.clone())
-                .collect(),
-            disc_number: track.disc_number,
-            track_number: track.track_number,
-            album_cover,
-            album_name: track.album.name,
-        });
+        tracks.push(spotify_track_from_full(track));
     }
 
     tracks
@@ -164,32 +209,443 @@ pub fn who_loves_podcasts_anyways(playable_items: Vec<PlaylistItem>) -> Vec<Spot
 
 pub async fn get_playlist_details(
     spotify_id: String,
-    client: &AuthCodeSpotify,
-) -> Option<SpotifyPlaylist> {
-    let playlist_id = match PlaylistId::from_id(spotify_id) {
-        Ok(id) => Ok(id),
-        Err(err) => Err(format!("Couldn't parse the given playlist id: {}", err)),
-    }
-    .unwrap();
+    client: &impl BaseClient,
+) -> Result<SpotifyPlaylist, Error> {
+    let playlist_id = PlaylistId::from_id(spotify_id.clone()).map_err(|err| {
+        Error::InvalidId(format!(
+            "Couldn't parse the playlist id {}: {}",
+            spotify_id, err
+        ))
+    })?;
 
+    // private playlists aren't found without the user's authorization
     let playlist = client
-        .playlist(playlist_id, None, None)
+        .playlist(playlist_id.clone(), None, None)
         .await
-        .map_err(|err| println!("Error while searching playlist of the given id: {}", err))
-        .unwrap();
+        .map_err(|err| {
+            Error::spotify(&format!("Playlist {} couldn't be fetched", spotify_id), err)
+        })?;
 
-    let tracks = who_loves_podcasts_anyways(playlist.tracks.items);
+    // the playlist only carries the first 100 items, the rest are fetched page by page
+    let items = if playlist.tracks.next.is_none() {
+        playlist.tracks.items
+    } else {
+        client
+            .playlist_items(playlist_id, None, None)
+            .try_collect::<Vec<PlaylistItem>>()
+            .await
+            .map_err(|err| {
+                Error::spotify(
+                    &format!("Tracks of playlist {} couldn't be fetched", spotify_id),
+                    err,
+                )
+            })?
+    };
+    let tracks = who_loves_podcasts_anyways(items);
     let mut cover_url: Vec<String> = Vec::new();
 
     for image in playlist.images {
-        println!("\nURL: {:?}\n", image.url);
+        debug!("Playlist cover: {}", image.url);
         cover_url.push(image.url);
     }
 
-    Some(SpotifyPlaylist {
+    Ok(SpotifyPlaylist {
         name: playlist.name,
         number_of_songs: playlist.tracks.total,
         tracks,
         cover_url,
     })
 }
+
+// the user's liked songs, downloaded like a playlist
+pub async fn get_library_details(client: &impl OAuthClient) -> Result<SpotifyPlaylist, Error> {
+    let saved_tracks = client
+        .current_user_saved_tracks(None)
+        .try_collect::<Vec<SavedTrack>>()
+        .await
+        .map_err(|err| Error::spotify("Saved tracks couldn't be fetched", err))?;
+
+    let tracks: Vec<SpotifyTrack> = saved_tracks
+        .into_iter()
+        .map(|saved| spotify_track_from_full(saved.track))
+        .collect();
+
+    Ok(SpotifyPlaylist {
+        name: "Liked Songs".to_string(),
+        number_of_songs: tracks.len() as u32,
+        tracks,
+        cover_url: Vec::new(),
+    })
+}
+
+fn search_result(kind: Spotify, id: String, name: String) -> SpotifySearchResult {
+    SpotifySearchResult {
+        link: SpotifyLink { kind, id },
+        name,
+        artists: Vec::new(),
+        year: None,
+        track_count: None,
+    }
+}
+
+// the release date is a year, a month or a day depending on its precision
+fn release_year(release_date: &str) -> String {
+    release_date.chars().take(4).collect()
+}
+
+// simplified albums carry no track count, the full albums are fetched for it twenty at a time
+async fn album_results(
+    albums: Vec<SimplifiedAlbum>,
+    client: &impl BaseClient,
+) -> Vec<SpotifySearchResult> {
+    let ids: Vec<AlbumId<'static>> = albums.iter().filter_map(|album| album.id.clone()).collect();
+    let mut track_counts: HashMap<String, u32> = HashMap::new();
+
+    for chunk in ids.chunks(20) {
+        match client.albums(chunk.to_vec(), None).await {
+            Ok(full_albums) => track_counts.extend(
+                full_albums
+                    .into_iter()
+                    .map(|album| (album.id.id().to_string(), album.tracks.total)),
+            ),
+            Err(err) => error!("Error while fetching the track counts of albums: {}", err),
+        }
+    }
+
+    albums
+        .into_iter()
+        .filter_map(|album| {
+            let id = album.id.as_ref()?.id().to_string();
+            let mut result = search_result(Spotify::Album, id.clone(), album.name);
+            result.artists = album
+                .artists
+                .into_iter()
+                .map(|artist| artist.name)
+                .collect();
+            result.year = album.release_date.as_deref().map(release_year);
+            result.track_count = track_counts.get(&id).copied();
+            Some(result)
+        })
+        .collect()
+}
+
+// free text search over spotify's catalogue, best matches first
+pub async fn search(
+    query: &str,
+    kind: Spotify,
+    limit: u32,
+    client: &impl BaseClient,
+) -> Result<Vec<SpotifySearchResult>, Error> {
+    let search_type = match kind {
+        Spotify::Track => SearchType::Track,
+        Spotify::Album => SearchType::Album,
+        Spotify::Artist => SearchType::Artist,
+        Spotify::Playlist => SearchType::Playlist,
+        Spotify::Library => return Err(Error::Search("The library can't be searched".to_string())),
+    };
+
+    // a rate limit or a refused authorization is worth telling apart from a failed search
+    let result = client
+        .search(query, search_type, None, None, Some(limit), None)
+        .await
+        .map_err(
+            |err| match Error::spotify(&format!("Searching for {} failed", query), err) {
+                err @ (Error::RateLimited(_) | Error::Auth(_)) => err,
+                err => Error::Search(err.to_string()),
+            },
+        )?;
+
+    let results = match result {
+        SearchResult::Tracks(page) => page
+            .items
+            .into_iter()
+            .map(|track| {
+                let year = track.album.release_date.as_deref().map(release_year);
+                let track = spotify_track_from_full(track);
+                let mut result = search_result(Spotify::Track, track.id, track.name);
+                result.artists = track.artists;
+                result.year = year;
+                result
+            })
+            .collect(),
+        SearchResult::Albums(page) => album_results(page.items, client).await,
+        SearchResult::Artists(page) => page
+            .items
+            .into_iter()
+            .map(|artist| search_result(Spotify::Artist, artist.id.id().to_string(), artist.name))
+            .collect(),
+        SearchResult::Playlists(page) => page
+            .items
+            .into_iter()
+            .map(|playlist| {
+                let mut result = search_result(
+                    Spotify::Playlist,
+                    playlist.id.id().to_string(),
+                    playlist.name,
+                );
+                result.artists = vec![playlist
+                    .owner
+                    .display_name
+                    .unwrap_or_else(|| playlist.owner.id.id().to_string())];
+                result.track_count = Some(playlist.tracks.total);
+                result
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(results)
+}
+
+pub async fn get_artist_details(
+    spotify_id: String,
+    client: &impl BaseClient,
+) -> Result<SpotifyArtist, Error> {
+    let artist_id = ArtistId::from_id(spotify_id.clone()).map_err(|err| {
+        Error::InvalidId(format!(
+            "Couldn't parse the artist id {}: {}",
+            spotify_id, err
+        ))
+    })?;
+
+    let artist = client.artist(artist_id.clone()).await.map_err(|err| {
+        Error::spotify(&format!("Artist {} couldn't be fetched", spotify_id), err)
+    })?;
+
+    // top tracks differ between countries and client credentials have none of their own
+    let market = Market::Country(Country::UnitedStates);
+    let top_tracks = match client.artist_top_tracks(artist_id, Some(market)).await {
+        Ok(tracks) => tracks.into_iter().map(spotify_track_from_full).collect(),
+        Err(err) => {
+            error!("Error while fetching the top tracks of the artist: {}", err);
+            Vec::new()
+        }
+    };
+
+    Ok(SpotifyArtist {
+        name: artist.name,
+        genres: artist.genres,
+        followers: artist.followers.total,
+        cover_url: artist.images.first().map(|image| image.url.clone()),
+        top_tracks,
+    })
+}
+
+// albums and singles of an artist, newest first as spotify returns them
+pub async fn get_artist_albums(
+    spotify_id: String,
+    client: &impl BaseClient,
+) -> Result<Vec<SpotifySearchResult>, Error> {
+    let artist_id = ArtistId::from_id(spotify_id.clone()).map_err(|err| {
+        Error::InvalidId(format!(
+            "Couldn't parse the artist id {}: {}",
+            spotify_id, err
+        ))
+    })?;
+
+    let albums = client
+        .artist_albums(artist_id, [AlbumType::Album, AlbumType::Single], None)
+        .try_collect::<Vec<SimplifiedAlbum>>()
+        .await
+        .map_err(|err| {
+            Error::spotify(
+                &format!("Albums of the artist {} couldn't be fetched", spotify_id),
+                err,
+            )
+        })?;
+
+    Ok(album_results(albums, client).await)
+}
+
+// a spotify client in whichever flow the run could authenticate with
+pub enum SpotifyClient {
+    // client credentials, public albums, tracks and playlists only
+    Public(ClientCredsSpotify),
+    // authorization code flow with the client secret
+    User(AuthCodeSpotify),
+    // authorization code flow with PKCE, for machines without the client secret
+    Pkce(AuthCodePkceSpotify),
+}
+
+macro_rules! with_client {
+    ($spotify_client:expr, $client:ident => $body:expr) => {
+        match $spotify_client {
+            SpotifyClient::Public($client) => $body,
+            SpotifyClient::User($client) => $body,
+            SpotifyClient::Pkce($client) => $body,
+        }
+    };
+}
+
+impl SpotifyClient {
+    pub fn is_public(&self) -> bool {
+        matches!(self, SpotifyClient::Public(_))
+    }
+
+    pub async fn track(&self, spotify_id: String) -> Result<SpotifyTrack, Error> {
+        with_client!(self, client => get_track_details(spotify_id, client).await)
+    }
+
+    pub async fn album(&self, spotify_id: String) -> Result<SpotifyAlbum, Error> {
+        with_client!(self, client => get_album_details(spotify_id, client).await)
+    }
+
+    pub async fn playlist(&self, spotify_id: String) -> Result<SpotifyPlaylist, Error> {
+        with_client!(self, client => get_playlist_details(spotify_id, client).await)
+    }
+
+    pub async fn artist(&self, spotify_id: String) -> Result<SpotifyArtist, Error> {
+        with_client!(self, client => get_artist_details(spotify_id, client).await)
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        kind: Spotify,
+        limit: u32,
+    ) -> Result<Vec<SpotifySearchResult>, Error> {
+        with_client!(self, client => search(query, kind, limit, client).await)
+    }
+
+    pub async fn artist_albums(
+        &self,
+        spotify_id: String,
+    ) -> Result<Vec<SpotifySearchResult>, Error> {
+        with_client!(self, client => get_artist_albums(spotify_id, client).await)
+    }
+
+    // display name of the authorized user, public clients have none
+    pub async fn user_name(&self) -> Option<String> {
+        let user = match self {
+            SpotifyClient::Public(_) => return None,
+            SpotifyClient::User(client) => client.me().await,
+            SpotifyClient::Pkce(client) => client.me().await,
+        };
+
+        match user {
+            Ok(user) => Some(user.display_name.unwrap_or(user.id.id().to_string())),
+            Err(err) => {
+                error!("User couldn't be fetched: {}", err);
+                None
+            }
+        }
+    }
+
+    pub async fn library(&self) -> Result<SpotifyPlaylist, Error> {
+        match self {
+            SpotifyClient::Public(_) => Err(Error::Auth(
+                "The library can't be read without the user's authorization!".to_string(),
+            )),
+            SpotifyClient::User(client) => get_library_details(client).await,
+            SpotifyClient::Pkce(client) => get_library_details(client).await,
+        }
+    }
+}
+
+// the clients of a run, each created on first use so the user is only asked to authorize when needed
+pub struct SpotifyClients {
+    credentials: SpotifyCredentials,
+    no_browser: bool,
+    account: String,
+    public: Option<SpotifyClient>,
+    user: Option<SpotifyClient>,
+}
+
+impl SpotifyClients {
+    pub fn new(cli_args: &CliConfig) -> Self {
+        SpotifyClients {
+            credentials: cli_args.credentials.clone(),
+            no_browser: cli_args.no_browser,
+            account: cli_args.account.clone(),
+            public: None,
+            user: None,
+        }
+    }
+
+    pub async fn public(&mut self) -> Result<&SpotifyClient, Error> {
+        let client = match self.public.take() {
+            Some(client) => client,
+            None => public_client(&self.credentials, self.no_browser, &self.account).await?,
+        };
+        Ok(self.public.insert(client))
+    }
+
+    pub async fn user(&mut self) -> Result<&SpotifyClient, Error> {
+        let client = match self.user.take() {
+            Some(client) => client,
+            None => user_authorization(&self.credentials, self.no_browser, &self.account).await?,
+        };
+        Ok(self.user.insert(client))
+    }
+}
+
+// private playlists fall back to the user's authorization, the clients are created on first use
+impl MetadataProvider for SpotifyClients {
+    fn resolve<'a>(
+        &'a mut self,
+        link: &'a SpotifyLink,
+    ) -> BoxFuture<'a, Result<SpotifyEntity, Error>> {
+        Box::pin(async move {
+            let id = link.id.clone();
+            match link.kind {
+                Spotify::Track => self
+                    .public()
+                    .await?
+                    .track(id)
+                    .await
+                    .map(SpotifyEntity::Track),
+                Spotify::Album => self
+                    .public()
+                    .await?
+                    .album(id)
+                    .await
+                    .map(SpotifyEntity::Album),
+                Spotify::Playlist => {
+                    let client = self.public().await?;
+                    match client.playlist(id.clone()).await {
+                        Ok(playlist) => Ok(playlist),
+                        // private playlists look missing or forbidden to the app, anything
+                        // else like a rate limit isn't solved by asking the user to log in
+                        Err(err @ (Error::NotFound(_) | Error::Auth(_))) if client.is_public() => {
+                            info!(
+                                "Playlist {} isn't public ({}), falling back to user authorization",
+                                id, err
+                            );
+                            self.user().await?.playlist(id).await
+                        }
+                        Err(err) => Err(err),
+                    }
+                    .map(SpotifyEntity::Playlist)
+                }
+                Spotify::Library => self
+                    .user()
+                    .await?
+                    .library()
+                    .await
+                    .map(SpotifyEntity::Playlist),
+                Spotify::Artist => self
+                    .public()
+                    .await?
+                    .artist(id)
+                    .await
+                    .map(SpotifyEntity::Artist),
+            }
+        })
+    }
+
+    fn search<'a>(
+        &'a mut self,
+        query: &'a str,
+        kind: Spotify,
+        limit: u32,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>> {
+        Box::pin(async move { self.public().await?.search(query, kind, limit).await })
+    }
+
+    fn artist_albums<'a>(
+        &'a mut self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<SpotifySearchResult>, Error>> {
+        Box::pin(async move { self.public().await?.artist_albums(id.to_string()).await })
+    }
+}
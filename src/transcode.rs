@@ -0,0 +1,142 @@
+use crate::{
+    error::Error,
+    models::cli::{Bitrate, Codec},
+};
+use log::info;
+use std::{fs::copy, path::Path, process};
+use tokio::process::Command;
+
+// how ffmpeg writes a codec: the muxer, since the extension isn't always one ffmpeg knows, the
+// encoder and the options it is tuned with
+struct Encoder {
+    format: &'static str,
+    encoder: &'static str,
+    options: &'static [&'static str],
+}
+
+fn encoder(codec: Codec) -> Encoder {
+    let (format, encoder, options): (_, _, &'static [&'static str]) = match codec {
+        Codec::MP3 | Codec::Mpa => ("mp3", "libmp3lame", &[]),
+        Codec::Aac => ("ipod", "aac", &[]),
+        Codec::Alac => ("ipod", "alac", &[]),
+        Codec::Opus => ("opus", "libopus", &["-vbr", "on"]),
+        Codec::Vorbis => ("ogg", "libvorbis", &[]),
+        Codec::Flac => ("flac", "flac", &["-compression_level", "8"]),
+        Codec::Wav => ("wav", "pcm_s16le", &[]),
+        Codec::Aiff => ("aiff", "pcm_s16be", &[]),
+    };
+    Encoder {
+        format,
+        encoder,
+        options,
+    }
+}
+
+// best and worst are the encoder's own highest and lowest quality where it has a scale for them,
+// a fixed bitrate otherwise. lossless codecs ignore the bitrate
+fn quality_args(codec: Codec, bitrate: Bitrate) -> Vec<String> {
+    let args = |flag: &str, value: String| vec![flag.to_string(), value];
+    match (codec, bitrate) {
+        (codec, _) if codec.is_lossless() => Vec::new(),
+        (Codec::MP3 | Codec::Mpa, Bitrate::Best) => args("-q:a", "0".to_string()),
+        (Codec::MP3 | Codec::Mpa, Bitrate::Worst) => args("-q:a", "9".to_string()),
+        (Codec::Vorbis, Bitrate::Best) => args("-q:a", "10".to_string()),
+        (Codec::Vorbis, Bitrate::Worst) => args("-q:a", "0".to_string()),
+        (Codec::Aac | Codec::Opus, Bitrate::Best) => args("-b:a", "256k".to_string()),
+        (Codec::Aac | Codec::Opus, Bitrate::Worst) => args("-b:a", "32k".to_string()),
+        (_, bitrate) => args("-b:a", format!("{}k", bitrate as u32)),
+    }
+}
+
+// converts the audio of input into output with ffmpeg, leaving the tags and pictures behind
+pub async fn transcode(
+    input: &Path,
+    output: &Path,
+    codec: Codec,
+    bitrate: Bitrate,
+) -> Result<(), Error> {
+    let Encoder {
+        format,
+        encoder,
+        options,
+    } = encoder(codec);
+    info!("Transcoding {:?} to {} at {}", input, codec, bitrate);
+
+    let result = Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+        .arg(input)
+        .args(["-map", "0:a:0", "-map_metadata", "-1", "-c:a", encoder])
+        .args(options)
+        .args(quality_args(codec, bitrate))
+        .args(["-f", format])
+        .arg(output)
+        .output()
+        .await
+        .map_err(|err| Error::Download(format!("ffmpeg couldn't be run: {}", err)))?;
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(Error::Download(format!(
+            "{:?} couldn't be transcoded to {}: {}",
+            input,
+            codec,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )))
+    }
+}
+
+// the audio a source fetched, in the codec of the run. a file that already is in the codec is
+// copied as it is when the best bitrate is asked for, anything else goes through ffmpeg once
+pub async fn encode(
+    input: &Path,
+    output: &Path,
+    codec: Codec,
+    bitrate: Bitrate,
+) -> Result<(), Error> {
+    if is_copied(input, codec, bitrate) {
+        info!("Copying {:?}, it already is {}", input, codec);
+        copy(input, output)
+            .map(|_| ())
+            .map_err(|err| Error::Io(format!("{:?} couldn't be copied: {}", input, err)))
+    } else {
+        transcode(input, output, codec, bitrate).await
+    }
+}
+
+pub fn is_copied(input: &Path, codec: Codec, bitrate: Bitrate) -> bool {
+    let same_codec = input
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(codec.extension()));
+
+    // an m4a holds either aac or alac, so it isn't trusted to be the codec by its extension
+    same_codec && matches!(bitrate, Bitrate::Best) && !matches!(codec, Codec::Aac | Codec::Alac)
+}
+
+// the cover as a jpeg that fits into size by size pixels, smaller covers are left as big as they are.
+// blocking, it is only called while tagging
+pub fn resize_cover(cover: &Path, size: u32) -> Result<Vec<u8>, Error> {
+    let scale = format!(
+        "scale=w='min(iw,{0})':h='min(ih,{0})':force_original_aspect_ratio=decrease",
+        size
+    );
+    let result = process::Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-i"])
+        .arg(cover)
+        .args(["-vf", &scale, "-frames:v", "1", "-q:v", "2"])
+        .args(["-c:v", "mjpeg", "-f", "image2pipe", "-"])
+        .output()
+        .map_err(|err| Error::Tagging(format!("ffmpeg couldn't be run: {}", err)))?;
+
+    if result.status.success() && !result.stdout.is_empty() {
+        Ok(result.stdout)
+    } else {
+        Err(Error::Tagging(format!(
+            "Cover {:?} couldn't be resized to {}px: {}",
+            cover,
+            size,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )))
+    }
+}
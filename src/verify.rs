@@ -0,0 +1,179 @@
+use crate::cli::Config;
+use crate::matcher::Candidate;
+use crate::models::cli::{Bitrate, Codec};
+use crate::models::spotify::SpotifyTrack;
+use lofty::file::FileType;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use log::warn;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+
+// how far the downloaded audio may drift from the length spotify reports
+pub const DURATION_TOLERANCE: Duration = Duration::from_secs(5);
+
+// constant bitrate codecs have to land within this fraction of the requested bitrate
+pub const BITRATE_TOLERANCE: f64 = 0.15;
+
+// checks that the downloaded file is the song spotify describes and in the format that was asked for
+pub async fn verify_track(
+    file_path: &Path,
+    spotify_song: &SpotifyTrack,
+    candidate: &Candidate,
+    cli_args: &Config,
+) -> Result<(), String> {
+    // by the content, the extension of some codecs like mpa isn't one lofty knows
+    let tagged_file = Probe::open(file_path)
+        .and_then(|probe| Ok(probe.guess_file_type()?))
+        .and_then(|probe| probe.read())
+        .map_err(|err| format!("Couldn't read {:?}: {}", file_path, err))?;
+
+    check_codec(tagged_file.file_type(), cli_args.codec)?;
+
+    let properties = tagged_file.properties();
+    if !candidate.pinned {
+        check_duration(properties.duration(), spotify_song.duration_ms)?;
+    }
+    check_bitrate(properties.audio_bitrate(), cli_args.codec, cli_args.bitrate)?;
+
+    check_decodes(file_path).await
+}
+
+fn check_codec(file_type: FileType, codec: Codec) -> Result<(), String> {
+    let expected = match codec {
+        Codec::MP3 | Codec::Mpa => FileType::Mpeg,
+        Codec::Flac => FileType::Flac,
+        Codec::Opus => FileType::Opus,
+        Codec::Aac | Codec::Alac => FileType::Mp4,
+        Codec::Vorbis => FileType::Vorbis,
+        Codec::Wav => FileType::Wav,
+        Codec::Aiff => FileType::Aiff,
+    };
+
+    if file_type == expected {
+        Ok(())
+    } else {
+        Err(format!("Expected a {} file, got {:?}", codec, file_type))
+    }
+}
+
+fn check_duration(duration: Duration, duration_ms: u32) -> Result<(), String> {
+    let expected = Duration::from_millis(u64::from(duration_ms));
+    let difference = duration.abs_diff(expected);
+
+    if difference <= DURATION_TOLERANCE {
+        Ok(())
+    } else {
+        Err(format!(
+            "Duration {}s differs from the spotify duration {}s",
+            duration.as_secs(),
+            expected.as_secs()
+        ))
+    }
+}
+
+// only mp3 is encoded at a constant bitrate, the other codecs are either lossless or variable
+fn check_bitrate(audio_bitrate: Option<u32>, codec: Codec, bitrate: Bitrate) -> Result<(), String> {
+    let expected = match (codec, bitrate) {
+        (Codec::MP3 | Codec::Mpa, Bitrate::Worst | Bitrate::Best) => return Ok(()),
+        (Codec::MP3 | Codec::Mpa, bitrate) => f64::from(bitrate as u32),
+        _ => return Ok(()),
+    };
+
+    let Some(actual) = audio_bitrate else {
+        return Err("Bitrate couldn't be read".to_string());
+    };
+
+    if (f64::from(actual) - expected).abs() <= expected * BITRATE_TOLERANCE {
+        Ok(())
+    } else {
+        Err(format!(
+            "Bitrate {}kbps doesn't match {}kbps",
+            actual, bitrate
+        ))
+    }
+}
+
+// decodes the whole file with ffmpeg, which reports truncated or corrupt audio on stderr
+async fn check_decodes(file_path: &Path) -> Result<(), String> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(file_path)
+        .args(["-f", "null", "-"])
+        .output()
+        .await;
+
+    match output {
+        Ok(output) if output.status.success() && output.stderr.is_empty() => Ok(()),
+        Ok(output) => Err(format!(
+            "Audio couldn't be decoded: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(err) => {
+            warn!("ffmpeg couldn't be run, skipping decode check: {}", err);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{remove_file, write};
+
+    #[test]
+    fn accepts_a_duration_within_the_tolerance() {
+        assert!(check_duration(Duration::from_secs(213), 213573).is_ok());
+        assert!(check_duration(Duration::from_secs(218), 213573).is_ok());
+        assert!(check_duration(Duration::from_secs(209), 213573).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_duration_outside_the_tolerance() {
+        let err = check_duration(Duration::from_secs(240), 213573).unwrap_err();
+        assert_eq!(err, "Duration 240s differs from the spotify duration 213s");
+        assert!(check_duration(Duration::from_secs(200), 213573).is_err());
+    }
+
+    #[test]
+    fn checks_the_bitrate_of_constant_bitrate_codecs() {
+        assert!(check_bitrate(Some(320), Codec::MP3, Bitrate::High).is_ok());
+        assert!(check_bitrate(Some(300), Codec::MP3, Bitrate::High).is_ok());
+        assert!(check_bitrate(Some(128), Codec::MP3, Bitrate::High).is_err());
+        assert!(check_bitrate(None, Codec::Mpa, Bitrate::Medium).is_err());
+    }
+
+    #[test]
+    fn skips_the_bitrate_of_other_codecs_and_of_best_or_worst() {
+        assert!(check_bitrate(Some(128), Codec::MP3, Bitrate::Best).is_ok());
+        assert!(check_bitrate(None, Codec::MP3, Bitrate::Worst).is_ok());
+        assert!(check_bitrate(Some(900), Codec::Flac, Bitrate::High).is_ok());
+        assert!(check_bitrate(None, Codec::Opus, Bitrate::Low).is_ok());
+    }
+
+    #[test]
+    fn checks_the_file_type_of_the_codec() {
+        assert!(check_codec(FileType::Mpeg, Codec::Mpa).is_ok());
+        assert!(check_codec(FileType::Mp4, Codec::Alac).is_ok());
+        let err = check_codec(FileType::Mpeg, Codec::Flac).unwrap_err();
+        assert_eq!(err, "Expected a flac file, got Mpeg");
+    }
+
+    #[tokio::test]
+    async fn rejects_audio_that_does_not_decode() {
+        let path = std::env::temp_dir().join(format!("verify-{}.mp3", std::process::id()));
+        write(&path, b"not audio").unwrap();
+
+        let result = check_decodes(&path).await;
+        remove_file(&path).unwrap();
+
+        // without ffmpeg the check is skipped
+        let ffmpeg = Command::new("ffmpeg").arg("-version").output().await;
+        if ffmpeg.is_ok() {
+            assert!(result.unwrap_err().starts_with("Audio couldn't be decoded"));
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+}